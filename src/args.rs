@@ -1,7 +1,9 @@
 use clap::Parser;
 
 use crate::subcommands::{
-    check::CheckArgs, new::NewArgs, publish::PublishArgs, run::RunArgs, use_cmd::UseArgs,
+    check::CheckArgs, info::InfoArgs, keygen::KeygenArgs, list::ListArgs, new::NewArgs,
+    publish::PublishArgs, remove::RemoveArgs, run::RunArgs, save::SaveArgs, search::SearchArgs,
+    trust::TrustArgs, use_cmd::UseArgs, verify::VerifyArgs,
 };
 
 /// All subcommands available to the user
@@ -18,7 +20,21 @@ pub enum Command {
     /// Checks that your bowl template is valid and publishes it
     Publish(PublishArgs),
     /// Saves a template locally without publishing it
-    Save,
+    Save(SaveArgs),
+    /// Lists the templates saved locally
+    List(ListArgs),
+    /// Shows a saved template without using it
+    Info(InfoArgs),
+    /// Removes saved versions of a template
+    Remove(RemoveArgs),
+    /// Searches the registry for templates
+    Search(SearchArgs),
+    /// Checks a bowlfile for damaged or truncated entries
+    Verify(VerifyArgs),
+    /// Generates a key pair for signing templates
+    Keygen(KeygenArgs),
+    /// Trusts templates signed by the given public key
+    Trust(TrustArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -31,4 +47,8 @@ pub struct Cli {
     /// Specifies authentication token with soup server
     #[arg(long, global = true)]
     pub token: Option<String>,
+
+    /// Soup registry to use, by its name in config.toml or its URL
+    #[arg(long, global = true)]
+    pub registry: Option<String>,
 }
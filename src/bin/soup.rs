@@ -0,0 +1,42 @@
+use std::{path::PathBuf, process::exit};
+
+use bowl::soup::Soup;
+use clap::Parser;
+use tiny_http::Server;
+
+/// Serves a registry of bowl templates from a directory
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Directory the templates and tokens.toml are kept in
+    #[arg(long, default_value = "soup")]
+    dir: PathBuf,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    addr: String,
+
+    /// Require a token for every request, not only for publishing
+    #[arg(long, action)]
+    private: bool,
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let soup = Soup::open(&args.dir, args.private)?;
+    let server = Server::http(&args.addr)
+        .map_err(|e| format!("Failed to listen on {}: {}", args.addr, e))?;
+    println!(
+        "Serving templates from {} on http://{}",
+        args.dir.display(),
+        args.addr
+    );
+    soup.serve(&server);
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        println!("{}", e);
+        exit(1);
+    }
+}
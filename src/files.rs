@@ -1,47 +1,247 @@
 use directories::ProjectDirs;
-use std::fs::{self, DirEntry, File};
-use std::io::Write;
-
-pub fn file_entries(entry: DirEntry) -> Result<Vec<DirEntry>, String> {
-    if entry.metadata().unwrap().is_dir() {
-        Ok(fs::read_dir(entry.path())
-            .map_err(|e| e.to_string())?
-            .map(|x| x.map_err(|e| e.to_string()))
-            .collect::<Result<Vec<DirEntry>, String>>()?
-            .into_iter()
-            .flat_map(file_entries)
-            .flatten()
-            .collect::<Vec<DirEntry>>())
-    } else {
-        Ok(vec![entry])
-    }
-}
-
-pub fn save_file_locally(filename: String, content: Vec<u8>) -> Result<(), String> {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "jackjohn7", "bowl") {
-        let mut p = proj_dirs.data_dir().to_path_buf();
-        fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
-        p.push(filename);
-        File::create(p)
-            .map_err(|e| format!("Error creating bowlfile: {}", e))?
-            .write_all(&content)
-            .map_err(|e| format!("Failed to write bowlfile: {}", e))?;
+use std::fs::{self, Metadata};
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(not(unix))]
+use crate::templates::symbols::{DEFAULT_DIR_MODE, DEFAULT_MODE};
+
+/// Join `relative` onto `root`, refusing paths that could end up
+/// outside of `root`
+pub fn safe_join(root: &Path, relative: &str) -> Result<PathBuf, String> {
+    let relative = relative.strip_prefix("./").unwrap_or(relative);
+    if relative.split('/').any(str::is_empty) {
+        return Err(format!("\"{}\" has an empty file name in it", relative));
+    }
+    if relative.contains('\0') {
+        return Err(format!(
+            "\"{}\" has a NUL byte in it",
+            relative.escape_debug()
+        ));
+    }
+    let mut parts = Vec::new();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => parts.push(part),
+            _ => return Err(format!("\"{}\" points outside of the project", relative)),
+        }
+    }
+    if parts.is_empty() {
+        return Err(format!("\"{}\" doesn't name a file", relative));
+    }
+    Ok(root.join(parts.iter().collect::<PathBuf>()))
+}
+
+/// Refuse a link at `link`, relative to the project, whose `target`
+/// would point outside of the project
+pub fn check_link(link: &str, target: &str) -> Result<(), String> {
+    let escapes = || format!("link {} points outside of the project", link);
+    // directories the link is in, which its target is relative to
+    let link = Path::new(link.strip_prefix("./").unwrap_or(link));
+    let mut depth = link
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .count()
+        .saturating_sub(1);
+    for component in Path::new(target).components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return Err(escapes()),
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to write `path` if a directory between `root` and it is a
+/// symlink. Links are only checked by their text, so a chain of them
+/// could otherwise lead a write outside of `root`.
+pub fn check_ancestors(root: &Path, path: &Path) -> Result<(), String> {
+    let relative = path
+        .strip_prefix(root)
+        .map_err(|_| format!("{} is outside of the project", path.display()))?;
+    let mut current = root.to_path_buf();
+    let mut parts = relative.components().peekable();
+    while let Some(part) = parts.next() {
+        // the entry itself may be a link, that's up to the caller
+        if parts.peek().is_none() {
+            break;
+        }
+        current.push(part);
+        if current.symlink_metadata().is_ok_and(|m| m.is_symlink()) {
+            return Err(format!(
+                "{} is a link, refusing to write {} through it",
+                current.display(),
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Permission bits kept in bowlfiles. Setuid, setgid and sticky bits
+/// are dropped so an archive can't hand them out.
+const MODE_MASK: u32 = 0o777;
+
+/// Unix permission bits of an entry, or a sensible default elsewhere
+pub fn file_mode(metadata: &Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & MODE_MASK
+    }
+    #[cfg(not(unix))]
+    {
+        if metadata.is_dir() {
+            DEFAULT_DIR_MODE
+        } else {
+            DEFAULT_MODE
+        }
+    }
+}
+
+/// Apply unix permission bits to `path`, ignoring any special bits.
+/// Does nothing elsewhere.
+pub fn set_mode(path: &Path, mode: u32) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & MODE_MASK))
+            .map_err(|e| format!("Failed to set permissions of {}: {}", path.display(), e))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
         Ok(())
-    } else {
-        Err("Failed to locate data directory".into())
-    }
-}
-
-pub fn get_file_locally(template: String) -> Result<Vec<u8>, String> {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "jackjohn7", "bowl") {
-        let mut p = proj_dirs.data_dir().to_path_buf();
-        fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
-        p.push(&template);
-        p.set_extension("bowl");
-        dbg!(&p);
-        let content = fs::read(p).map_err(|e| format!("Failed to read bowlfile: {}", e))?;
-        Ok(content)
-    } else {
-        Err("Failed to locate data directory".into())
+    }
+}
+
+/// Create a symlink at `path` pointing to `target`
+pub fn create_symlink(target: &str, path: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, path);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_file(target, path);
+    #[cfg(not(any(unix, windows)))]
+    let result: std::io::Result<()> = Err(std::io::ErrorKind::Unsupported.into());
+
+    result.map_err(|e| format!("Failed to create symlink {}: {}", path.display(), e))
+}
+
+/// Where bowl's data, config and cache directories are on this system
+fn project_dirs() -> Result<ProjectDirs, String> {
+    ProjectDirs::from("com", "jackjohn7", "bowl").ok_or("Failed to locate home directory".into())
+}
+
+/// Directory bowl keeps its data in
+pub fn data_dir() -> Result<PathBuf, String> {
+    let p = project_dirs()?.data_dir().to_path_buf();
+    fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
+    Ok(p)
+}
+
+/// Where the user's settings are read from (see `settings.rs`)
+pub fn config_file() -> Result<PathBuf, String> {
+    Ok(project_dirs()?.config_dir().join("config.toml"))
+}
+
+/// Directory of the local template store (see `store.rs`)
+pub fn templates_dir() -> Result<PathBuf, String> {
+    let p = data_dir()?.join("templates");
+    fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
+    Ok(p)
+}
+
+/// Directory of public keys whose signatures `bowl use` accepts, kept
+/// alongside the local store
+pub fn trusted_keys_dir() -> Result<PathBuf, String> {
+    let p = project_dirs()?.data_dir().join("trusted_keys");
+    fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
+    Ok(p)
+}
+
+/// Directory that scripts of templates are extracted to for `bowl run`
+pub fn scripts_cache_dir() -> Result<PathBuf, String> {
+    let p = project_dirs()?.cache_dir().join("scripts");
+    fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
+    Ok(p)
+}
+
+/// Directory that bowlfiles downloaded from a registry are kept in
+pub fn registry_cache_dir() -> Result<PathBuf, String> {
+    let p = project_dirs()?.cache_dir().join("registry");
+    fs::create_dir_all(&p).map_err(|e| format!("Failed to make directory: {}", e))?;
+    Ok(p)
+}
+
+/// Paths of every key in the trusted keys store
+pub fn trusted_key_paths() -> Result<Vec<PathBuf>, String> {
+    Ok(fs::read_dir(trusted_keys_dir()?)
+        .map_err(|e| format!("Failed to read trusted keys: {}", e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "pub"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_join() {
+        let root = Path::new("out");
+        assert_eq!(
+            safe_join(root, "./cmd/my_app/main.go").unwrap(),
+            Path::new("out/cmd/my_app/main.go")
+        );
+        for path in [
+            "./src/com/../org/App.java",
+            "./a\0b",
+            "../evil",
+            "./a/../../evil",
+            "/etc/passwd",
+            "./cmd//main.go",
+            "./",
+            ".",
+        ] {
+            assert!(safe_join(root, path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_check_link() {
+        assert!(check_link("./link", "bin/blob").is_ok());
+        assert!(check_link("./docs/readme", "../README.md").is_ok());
+        assert!(check_link("./a/b/c", "../../x/../y").is_ok());
+        assert!(check_link("./link", "../outside").is_err());
+        assert!(check_link("./docs/readme", "../../.bashrc").is_err());
+        assert!(check_link("./link", "/etc/passwd").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_mode_drops_special_bits() {
+        let p = std::env::temp_dir().join(format!("bowl-mode-{}", std::process::id()));
+        fs::write(&p, "").unwrap();
+        set_mode(&p, 0o6755).unwrap();
+        let mode = file_mode(&fs::metadata(&p).unwrap());
+        fs::remove_file(&p).unwrap();
+        assert_eq!(mode, 0o755);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_ancestors() {
+        let root = std::env::temp_dir().join(format!("bowl-ancestors-{}", std::process::id()));
+        fs::create_dir_all(root.join("a")).unwrap();
+        create_symlink("..", &root.join("a/l")).unwrap();
+
+        assert!(check_ancestors(&root, &root.join("a/file")).is_ok());
+        assert!(check_ancestors(&root, &root.join("a/l")).is_ok());
+        assert!(check_ancestors(&root, &root.join("new/dir/file")).is_ok());
+        assert!(check_ancestors(&root, &root.join("a/l/x")).is_err());
+        assert!(check_ancestors(&root, Path::new("/etc/passwd")).is_err());
+        fs::remove_dir_all(&root).unwrap();
     }
 }
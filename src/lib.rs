@@ -0,0 +1,9 @@
+pub mod args;
+pub mod files;
+pub mod patterns;
+pub mod registry;
+pub mod settings;
+pub mod soup;
+pub mod store;
+pub mod subcommands;
+pub mod templates;
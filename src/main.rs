@@ -1,26 +1,38 @@
-pub mod args;
-pub mod files;
-pub mod subcommands;
-pub mod templates;
-
 use std::process::exit;
 
-use args::Cli;
-use clap::Parser;
-use subcommands::{
-    check::handle_check, new::handle_new, publish::handle_publish, run::handle_run,
-    use_cmd::handle_use,
+use bowl::{
+    args::{self, Cli},
+    settings::Remote,
+    subcommands::{
+        check::handle_check, info::handle_info, keygen::handle_keygen, list::handle_list,
+        new::handle_new, publish::handle_publish, remove::handle_remove, run::handle_run,
+        save::handle_save, search::handle_search, trust::handle_trust, use_cmd::handle_use,
+        verify::handle_verify,
+    },
 };
+use clap::Parser;
 
 fn main() {
-    let args = Cli::parse();
-    if let Err(e) = match args.command {
-        args::Command::Use(args) => handle_use(args),
+    let Cli {
+        command,
+        token,
+        registry,
+    } = Cli::parse();
+    let remote = Remote { registry, token };
+    if let Err(e) = match command {
+        args::Command::Use(args) => handle_use(args, &remote),
         args::Command::Run(args) => handle_run(args),
         args::Command::New(args) => handle_new(args),
         args::Command::Check(args) => handle_check(args),
-        args::Command::Publish(args) => handle_publish(args),
-        args::Command::Save => todo!(),
+        args::Command::Publish(args) => handle_publish(args, &remote),
+        args::Command::Save(args) => handle_save(args),
+        args::Command::List(args) => handle_list(args),
+        args::Command::Info(args) => handle_info(args),
+        args::Command::Remove(args) => handle_remove(args),
+        args::Command::Search(args) => handle_search(args, &remote),
+        args::Command::Verify(args) => handle_verify(args),
+        args::Command::Keygen(args) => handle_keygen(args),
+        args::Command::Trust(args) => handle_trust(args),
     } {
         println!("{}", e);
         exit(1);
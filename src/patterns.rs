@@ -0,0 +1,229 @@
+//! Which files of a project are packed into its bowlfile
+//!
+//! Ignore patterns use gitignore syntax, so `target` leaves out every
+//! `target` directory, `/target` only the one at the root, `**/*.log`
+//! matches at any depth and `!keep.log` includes a file again.
+//!
+//! Version control data, build output and OS junk are left out of every
+//! template unless bowl.toml says otherwise.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bowl_core::config::Config;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::templates::config::{Files, TemplateConfig};
+
+/// File listing more patterns to leave out of a project's bowlfile
+pub const BOWLIGNORE: &str = ".bowlignore";
+
+/// Left out unless `[files] default_excludes` replaces them or a later
+/// pattern brings a path back with `!`
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    "target/",
+    "node_modules/",
+    ".DS_Store",
+    "._*",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// Files and bytes left out of a bowlfile
+#[derive(Debug, Default, PartialEq)]
+pub struct Excluded {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Excluded {
+    /// Count everything at `path`, which isn't followed if it's a link
+    fn add(&mut self, path: &Path) -> Result<(), String> {
+        let metadata = fs::symlink_metadata(path).map_err(|e| e.to_string())?;
+        if !metadata.is_dir() {
+            self.files += 1;
+            self.bytes += metadata.len();
+            return Ok(());
+        }
+        for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+            self.add(&entry.map_err(|e| e.to_string())?.path())?;
+        }
+        Ok(())
+    }
+}
+
+/// Decides which paths under a project root are left out of its bowlfile
+pub struct Ignore {
+    matcher: Gitignore,
+}
+
+impl Ignore {
+    /// Read the default excludes, the `ignore` patterns from bowl.toml,
+    /// then the project's `.gitignore` when `[files] gitignore` is set and
+    /// then its `.bowlignore`. The last pattern matching a path decides,
+    /// like in git.
+    pub fn new(root: &Path, patterns: &[String], files: &Files) -> Result<Self, String> {
+        let mut builder = GitignoreBuilder::new(root);
+        let defaults = match &files.default_excludes {
+            Some(defaults) => defaults.clone(),
+            None => DEFAULT_EXCLUDES.iter().map(|p| p.to_string()).collect(),
+        };
+        for pattern in defaults.iter().chain(patterns) {
+            // `./target` used to be the way to name a path at the root
+            let pattern = match pattern.strip_prefix("./") {
+                Some(rest) => format!("/{}", rest),
+                None => pattern.clone(),
+            };
+            builder
+                .add_line(None, &pattern)
+                .map_err(|e| format!("Invalid ignore pattern \"{}\": {}", pattern, e))?;
+        }
+        for name in [files.gitignore.then_some(".gitignore"), Some(BOWLIGNORE)]
+            .into_iter()
+            .flatten()
+        {
+            let p = root.join(name);
+            if !p.is_file() {
+                continue;
+            }
+            if let Some(e) = builder.add(&p) {
+                return Err(format!("Invalid pattern in {}: {}", name, e));
+            }
+        }
+        let matcher = builder
+            .build()
+            .map_err(|e| format!("Invalid ignore pattern: {}", e))?;
+        Ok(Self { matcher })
+    }
+
+    /// Whether `path`, relative to the root, is left out
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher.matched(path, is_dir).is_ignore()
+    }
+}
+
+/// Recursively list the entries under `dir` that aren't ignored,
+/// counting the ones that are in `excluded`. Symlinks are listed rather
+/// than followed and empty directories are kept so that they can be
+/// recreated.
+pub fn walk(
+    dir: &Path,
+    ignore: &Ignore,
+    paths: &mut Vec<PathBuf>,
+    excluded: &mut Excluded,
+) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let is_dir = entry.file_type().map_err(|e| e.to_string())?.is_dir();
+        if ignore.is_ignored(&path, is_dir) {
+            excluded.add(&path)?;
+            continue;
+        }
+        let mut children = match is_dir {
+            true => fs::read_dir(&path).map_err(|e| e.to_string())?,
+            false => {
+                paths.push(path);
+                continue;
+            }
+        };
+        if children.next().is_some() {
+            walk(&path, ignore, paths, excluded)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Paths of everything the project in the current directory packs into
+/// its bowlfile, shared by `bowl publish` and `bowl check`. Reports what
+/// was left out.
+pub fn project_paths(
+    config: &Config,
+    template_config: &TemplateConfig,
+) -> Result<Vec<PathBuf>, String> {
+    let root = Path::new(".");
+    let patterns = config.options.ignore.clone().unwrap_or_default();
+    let ignore = Ignore::new(root, &patterns, &template_config.files)?;
+    let mut paths = Vec::new();
+    let mut excluded = Excluded::default();
+    walk(root, &ignore, &mut paths, &mut excluded)?;
+    if excluded.files > 0 {
+        println!(
+            "Excluded {} files ({} bytes)",
+            excluded.files, excluded.bytes
+        );
+    }
+
+    // scripts used by commands have to be packed along with the template
+    for script in template_config.scripts() {
+        if !paths.contains(&root.join(script)) {
+            return Err(format!("Script \"{}\" isn't part of the template", script));
+        }
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_patterns() {
+        let patterns = [
+            "target",
+            "./dist",
+            "**/*.log",
+            "!keep.log",
+            "docs/*.tmp",
+            "cache/",
+        ]
+        .map(String::from);
+        let files = Files {
+            default_excludes: Some(Vec::new()),
+            ..Files::default()
+        };
+        let ignore = Ignore::new(Path::new("."), &patterns, &files).unwrap();
+        let ignored = |path: &str, is_dir| ignore.is_ignored(Path::new(path), is_dir);
+
+        assert!(ignored("./target", true));
+        assert!(ignored("./crates/a/target", true));
+        assert!(ignored("./dist", true));
+        assert!(!ignored("./src/dist", true));
+        assert!(ignored("./a/b/debug.log", false));
+        assert!(!ignored("./keep.log", false));
+        assert!(ignored("./docs/x.tmp", false));
+        assert!(!ignored("./docs/sub/x.tmp", false));
+        assert!(ignored("./cache", true));
+        assert!(!ignored("./cache", false));
+        assert!(!ignored("./src/main.rs", false));
+    }
+
+    #[test]
+    fn test_default_excludes() {
+        let defaults = Ignore::new(Path::new("."), &[], &Files::default()).unwrap();
+        assert!(defaults.is_ignored(Path::new("./.git"), true));
+        assert!(defaults.is_ignored(Path::new("./app/node_modules"), true));
+        assert!(defaults.is_ignored(Path::new("./src/.DS_Store"), false));
+        assert!(!defaults.is_ignored(Path::new("./src/main.rs"), false));
+
+        // brought back by a later pattern
+        let ignore = Ignore::new(Path::new("."), &["!target".into()], &Files::default()).unwrap();
+        assert!(!ignore.is_ignored(Path::new("./target"), true));
+
+        // or replaced altogether
+        let files = Files {
+            default_excludes: Some(vec![".git".into()]),
+            ..Files::default()
+        };
+        let ignore = Ignore::new(Path::new("."), &[], &files).unwrap();
+        assert!(ignore.is_ignored(Path::new("./.git"), true));
+        assert!(!ignore.is_ignored(Path::new("./node_modules"), true));
+    }
+}
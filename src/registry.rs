@@ -0,0 +1,497 @@
+//! Client for a Soup registry
+//!
+//! Routes, relative to the registry's base URL:
+//!
+//! GET  /api/templates?q=&page=&per_page=          templates, newest first
+//! GET  /api/templates/<owner>/<name>              a template and its versions
+//! GET  /api/templates/<owner>/<name>/<version>    the bowlfile of a version
+//! POST /api/templates                             publish a bowlfile
+//!
+//! Everything but bowlfiles is JSON. Publishing needs an
+//! `Authorization: Bearer <token>` header, which is also sent with every
+//! other request when a token is given so private templates can be read.
+//! Failed requests answer with a non 2xx status and `{"error": "..."}`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use semver::Version;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    files::registry_cache_dir,
+    store::{check_name, pick_version},
+    templates::digest::{to_hex, HashingWriter},
+};
+
+/// Registry used when none is given. Until there is a public Soup
+/// instance this is one served by `soup` on this machine.
+pub const DEFAULT_REGISTRY: &str = "http://localhost:7878";
+
+/// Bowlfiles bigger than this are refused by the registry
+pub const MAX_BOWLFILE_LEN: u64 = 256 * 1024 * 1024;
+
+/// One published version of a template
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VersionInfo {
+    pub version: Version,
+    /// SHA-256 of the bowlfile, as hex
+    pub digest: String,
+    /// Length of the bowlfile in bytes
+    pub size: u64,
+    /// Seconds since the unix epoch
+    pub published_at: u64,
+}
+
+/// A template and every version of it, oldest first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemplateInfo {
+    pub owner: String,
+    pub name: String,
+    /// The `source` of the latest version from its bowl.toml
+    pub source: String,
+    pub description: String,
+    pub versions: Vec<VersionInfo>,
+}
+
+/// What the registry answers to a publish
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Published {
+    pub owner: String,
+    pub name: String,
+    pub version: Version,
+}
+
+/// A template as listed by a search
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemplateSummary {
+    pub owner: String,
+    pub name: String,
+    /// The latest version
+    pub version: Version,
+    pub description: String,
+    pub source: String,
+    /// When the latest version was published, in seconds since the unix epoch
+    pub published_at: u64,
+}
+
+/// One page of templates matching a search
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchResults {
+    pub templates: Vec<TemplateSummary>,
+    pub page: usize,
+    pub per_page: usize,
+    /// How many templates match across every page
+    pub total: usize,
+}
+
+/// Body of a failed request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// Split `owner/name@version` into its parts
+pub fn parse_registry_spec(spec: &str) -> Result<(&str, &str, Option<&str>), String> {
+    let (path, version) = match spec.split_once('@') {
+        Some((path, version)) => (path, Some(version)),
+        None => (spec, None),
+    };
+    let Some((owner, name)) = path.split_once('/') else {
+        return Err(format!(
+            "Templates in a registry are named owner/name, like jackjohn7/{}\nPass --local to use a saved template",
+            path
+        ));
+    };
+    check_name(owner)?;
+    check_name(name)?;
+    Ok((owner, name, version))
+}
+
+/// A Soup registry at some base URL
+pub struct Registry {
+    base_url: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Registry {
+    pub fn new(base_url: &str, token: Option<String>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            token,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    /// Turn a failed request into a message saying what went wrong
+    fn error(&self, path: &str, e: ureq::Error) -> String {
+        match e {
+            ureq::Error::Status(status, response) => {
+                let reason = response.status_text().to_owned();
+                let message = response
+                    .into_string()
+                    .ok()
+                    .and_then(|body| serde_json::from_str::<ErrorBody>(&body).ok())
+                    .map_or(reason, |body| body.error);
+                let hint = match status {
+                    401 | 403 if self.token.is_none() => {
+                        "\nPass --token or set one in config.toml to authenticate"
+                    }
+                    _ => "",
+                };
+                format!(
+                    "Registry answered {} for {}: {}{}",
+                    status, path, message, hint
+                )
+            }
+            ureq::Error::Transport(e) => {
+                format!("Couldn't reach registry {}: {}", self.base_url, e)
+            }
+        }
+    }
+
+    fn json<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.request("GET", path)
+            .call()
+            .map_err(|e| self.error(path, e))?
+            .into_json()
+            .map_err(|e| format!("Invalid answer from registry for {}: {}", path, e))
+    }
+
+    /// Search the templates in the registry, most recently published
+    /// first. `None` means the registry couldn't be reached.
+    pub fn search(
+        &self,
+        query: &str,
+        page: usize,
+        per_page: usize,
+    ) -> Result<Option<SearchResults>, String> {
+        let path = "/api/templates";
+        let response = self
+            .request("GET", path)
+            .query("q", query)
+            .query("page", &page.to_string())
+            .query("per_page", &per_page.to_string())
+            .call();
+        match response {
+            Ok(response) => response
+                .into_json()
+                .map(Some)
+                .map_err(|e| format!("Invalid answer from registry for {}: {}", path, e)),
+            Err(ureq::Error::Transport(_)) => Ok(None),
+            Err(e) => Err(self.error(path, e)),
+        }
+    }
+
+    /// Look up a template and its versions
+    pub fn template(&self, owner: &str, name: &str) -> Result<TemplateInfo, String> {
+        self.json(&format!("/api/templates/{}/{}", owner, name))
+    }
+
+    /// Download the bowlfile of one version of a template, returning
+    /// a reader of its content
+    pub fn download(
+        &self,
+        owner: &str,
+        name: &str,
+        version: &Version,
+    ) -> Result<impl Read + Send, String> {
+        let path = format!("/api/templates/{}/{}/{}", owner, name, version);
+        let response = self
+            .request("GET", &path)
+            .call()
+            .map_err(|e| self.error(&path, e))?;
+        Ok(response.into_reader())
+    }
+
+    /// Upload the `len` byte bowlfile read from `bowlfile`. The registry
+    /// reads its name and version from the bowl.toml inside, and its
+    /// owner from the token.
+    pub fn publish<R: Read>(&self, bowlfile: R, len: u64) -> Result<Published, String> {
+        let path = "/api/templates";
+        if self.token.is_none() {
+            return Err(
+                "Publishing to a registry needs a token, pass --token or set one in config.toml"
+                    .into(),
+            );
+        }
+        self.request("POST", path)
+            .set("Content-Type", "application/octet-stream")
+            .set("Content-Length", &len.to_string())
+            .send(bowlfile)
+            .map_err(|e| self.error(path, e))?
+            .into_json()
+            .map_err(|e| format!("Invalid answer from registry for {}: {}", path, e))
+    }
+}
+
+/// Whether `digest` is a SHA-256 in lowercase hex, which is all a digest
+/// from a registry may be before it's used as a file name
+fn is_hex_digest(digest: &str) -> bool {
+    digest.len() == 64
+        && digest
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Stream the download of `owner/name@version` into `partial`, checking
+/// it against the size and digest the registry gave
+fn download_to(
+    registry: &Registry,
+    owner: &str,
+    name: &str,
+    published: &VersionInfo,
+    partial: &Path,
+) -> Result<(), String> {
+    let version = &published.version;
+    let write_err = |e: io::Error| format!("Failed to cache download: {}", e);
+    let mut download = registry
+        .download(owner, name, version)?
+        .take(published.size + 1);
+    let file = File::create(partial).map_err(write_err)?;
+    let mut out = HashingWriter::new(BufWriter::new(file));
+    let copied = io::copy(&mut download, &mut out)
+        .map_err(|e| format!("Failed to download {}/{} {}: {}", owner, name, version, e))?;
+    out.flush().map_err(write_err)?;
+    if copied != published.size || to_hex(&out.finalize()) != published.digest {
+        return Err(format!(
+            "Download of {}/{} {} doesn't match the digest the registry gave",
+            owner, name, version
+        ));
+    }
+    Ok(())
+}
+
+/// Fetch the template asked for by `owner/name@version`, reusing an
+/// earlier download of the same bowlfile
+pub fn fetch(registry: &Registry, spec: &str) -> Result<File, String> {
+    let (owner, name, requested) = parse_registry_spec(spec)?;
+    let info = registry.template(owner, name)?;
+    let versions = info
+        .versions
+        .iter()
+        .map(|v| v.version.clone())
+        .collect::<Vec<Version>>();
+    let version = pick_version(&versions, requested)
+        .map_err(|e| format!("No published version of {}/{} {}", owner, name, e))?;
+    let published = info
+        .versions
+        .iter()
+        .find(|v| v.version == *version)
+        .unwrap();
+
+    if !is_hex_digest(&published.digest) {
+        return Err(format!(
+            "Registry gave an invalid digest for {}/{} {}",
+            owner, name, version
+        ));
+    }
+    // downloads are kept by digest, so a cached one is known to be intact
+    let p = registry_cache_dir()?.join(format!("{}.bowl", published.digest));
+    if !p.exists() {
+        println!("Downloading {}/{} {}", owner, name, version);
+        let partial = p.with_extension("partial");
+        if let Err(e) = download_to(registry, owner, name, published, &partial) {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+        fs::rename(&partial, &p).map_err(|e| format!("Failed to cache download: {}", e))?;
+    }
+    File::open(p).map_err(|e| format!("Failed to read bowlfile: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::digest::digest;
+    use std::thread;
+    use tiny_http::{Header, Response, Server};
+
+    /// Serve `count` requests, answering each with `respond`, and return
+    /// the base URL of the server
+    fn mock<F>(count: usize, respond: F) -> String
+    where
+        F: Fn(&mut tiny_http::Request) -> (u16, Vec<u8>) + Send + 'static,
+    {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            for mut request in server.incoming_requests().take(count) {
+                let (status, body) = respond(&mut request);
+                let json = Header::from_bytes("Content-Type", "application/json").unwrap();
+                let response = Response::from_data(body)
+                    .with_status_code(status)
+                    .with_header(json);
+                request.respond(response).unwrap();
+            }
+        });
+        url
+    }
+
+    fn info() -> TemplateInfo {
+        let version = |v: &str| VersionInfo {
+            version: Version::parse(v).unwrap(),
+            digest: to_hex(&digest(v.as_bytes())),
+            size: v.len() as u64,
+            published_at: 0,
+        };
+        TemplateInfo {
+            owner: "jack".into(),
+            name: "api".into(),
+            source: "https://example.com/api".into(),
+            description: "An API".into(),
+            versions: vec![version("1.0.0"), version("1.1.0")],
+        }
+    }
+
+    #[test]
+    fn test_template_and_download() {
+        let url = mock(2, |request| match request.url() {
+            "/api/templates/jack/api" => (200, serde_json::to_vec(&info()).unwrap()),
+            "/api/templates/jack/api/1.1.0" => (200, b"1.1.0".to_vec()),
+            _ => (404, br#"{"error": "not found"}"#.to_vec()),
+        });
+        let registry = Registry::new(&url, None);
+
+        assert_eq!(registry.template("jack", "api").unwrap(), info());
+        let version = Version::parse("1.1.0").unwrap();
+        let mut bowlfile = Vec::new();
+        registry
+            .download("jack", "api", &version)
+            .unwrap()
+            .read_to_end(&mut bowlfile)
+            .unwrap();
+        assert_eq!(bowlfile, b"1.1.0");
+    }
+
+    #[test]
+    fn test_publish_sends_token() {
+        let url = mock(1, |request| {
+            let authorized = request
+                .headers()
+                .iter()
+                .any(|h| h.field.equiv("Authorization") && h.value == "Bearer secret");
+            let mut body = Vec::new();
+            request.as_reader().read_to_end(&mut body).unwrap();
+            match (authorized, request.method().as_str(), body.as_slice()) {
+                (true, "POST", b"bowlfile") => (
+                    201,
+                    serde_json::to_vec(&Published {
+                        owner: "jack".into(),
+                        name: "api".into(),
+                        version: Version::new(1, 2, 0),
+                    })
+                    .unwrap(),
+                ),
+                _ => (401, br#"{"error": "bad token"}"#.to_vec()),
+            }
+        });
+
+        let published = Registry::new(&url, Some("secret".into()))
+            .publish(&b"bowlfile"[..], 8)
+            .unwrap();
+        assert_eq!(published.version, Version::new(1, 2, 0));
+
+        // nothing is sent without a token
+        assert!(Registry::new(&url, None)
+            .publish(&b"bowlfile"[..], 8)
+            .is_err());
+    }
+
+    #[test]
+    fn test_errors_are_surfaced() {
+        let url = mock(2, |request| match request.url() {
+            "/api/templates/jack/gone" => (404, br#"{"error": "no template jack/gone"}"#.to_vec()),
+            _ => (500, b"oops".to_vec()),
+        });
+        let registry = Registry::new(&url, None);
+
+        let err = registry.template("jack", "gone").unwrap_err();
+        assert!(
+            err.contains("404") && err.contains("no template jack/gone"),
+            "{}",
+            err
+        );
+        let err = registry.template("jack", "broken").unwrap_err();
+        assert!(err.contains("500"), "{}", err);
+
+        let err = Registry::new("http://127.0.0.1:1", None)
+            .template("jack", "api")
+            .unwrap_err();
+        assert!(err.contains("Couldn't reach registry"), "{}", err);
+    }
+
+    #[test]
+    fn test_search() {
+        let url = mock(1, |request| {
+            let results = SearchResults {
+                templates: Vec::new(),
+                page: 2,
+                per_page: 5,
+                total: 5,
+            };
+            match request.url() {
+                "/api/templates?q=axum+askama&page=2&per_page=5" => {
+                    (200, serde_json::to_vec(&results).unwrap())
+                }
+                _ => (400, br#"{"error": "bad query"}"#.to_vec()),
+            }
+        });
+
+        let results = Registry::new(&url, None)
+            .search("axum askama", 2, 5)
+            .unwrap();
+        assert_eq!(results.unwrap().total, 5);
+        // offline is told apart from failures so callers can fall back
+        let results = Registry::new("http://127.0.0.1:1", None).search("axum", 1, 5);
+        assert_eq!(results, Ok(None));
+    }
+
+    #[test]
+    fn test_fetch_rejects_bad_digest() {
+        let url = mock(1, |_| {
+            let mut info = info();
+            info.versions[1].digest = "../../templates/index".into();
+            (200, serde_json::to_vec(&info).unwrap())
+        });
+        let err = fetch(&Registry::new(&url, None), "jack/api@1.1.0").unwrap_err();
+        assert!(err.contains("invalid digest"), "{}", err);
+        assert!(is_hex_digest(&to_hex(&digest(b"1.0.0"))));
+        assert!(!is_hex_digest(&to_hex(&digest(b"1.0.0")).to_uppercase()));
+    }
+
+    #[test]
+    fn test_parse_registry_spec() {
+        assert_eq!(
+            parse_registry_spec("jack/api@1.2").unwrap(),
+            ("jack", "api", Some("1.2"))
+        );
+        assert_eq!(
+            parse_registry_spec("jack/api").unwrap(),
+            ("jack", "api", None)
+        );
+        assert!(parse_registry_spec("api").is_err());
+        assert!(parse_registry_spec("jack/../api").is_err());
+    }
+}
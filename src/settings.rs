@@ -0,0 +1,313 @@
+//! User settings, read from `config.toml` in bowl's config directory
+//!
+//! Registries are given names there, each with its URL, the token used
+//! with it and the scopes it serves:
+//!
+//! ```toml
+//! default = "public"
+//!
+//! [registries.public]
+//! url = "https://soup.example.com"
+//!
+//! [registries.corp]
+//! url = "https://soup.corp.example"
+//! token = "..."
+//! scopes = ["corp", "corp-labs"]
+//! ```
+//!
+//! A registry is picked for a template in this order:
+//!
+//! 1. `--registry`, as a name from the config or a URL
+//! 2. for `@scope/name`, or a template published with `scope` under
+//!    `[template]` in bowl.toml, the registry listing the scope, or the
+//!    one named after it
+//! 3. for `bowl publish`, `registry` under `[template]` in bowl.toml
+//! 4. `default` from the config
+//! 5. `registry::DEFAULT_REGISTRY`
+//!
+//! `--token` replaces the token of whichever registry is picked. A
+//! registry given by URL uses the token of the configured registry with
+//! that URL, if there is one.
+
+use std::{collections::BTreeMap, fs, io};
+
+use serde::Deserialize;
+
+use crate::{
+    files::config_file,
+    registry::{Registry, DEFAULT_REGISTRY},
+};
+
+/// A registry named in the config
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RegistryConfig {
+    pub url: String,
+    pub token: Option<String>,
+    /// Templates named `@scope/name` with one of these scopes come from
+    /// this registry
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Everything in the config file
+#[derive(Deserialize, Debug, Default)]
+pub struct Settings {
+    /// Name of the registry used when no other is picked
+    pub default: Option<String>,
+    #[serde(default)]
+    pub registries: BTreeMap<String, RegistryConfig>,
+}
+
+/// A registry that was picked, and the token to use with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl Target {
+    pub fn connect(self) -> Registry {
+        Registry::new(&self.url, self.token)
+    }
+}
+
+/// Split `@scope/name` into its scope and the rest
+pub fn parse_scope(spec: &str) -> Option<(&str, &str)> {
+    spec.strip_prefix('@')?.split_once('/')
+}
+
+impl Settings {
+    /// Read the config file, which doesn't have to exist
+    pub fn load() -> Result<Self, String> {
+        let p = config_file()?;
+        match fs::read_to_string(&p) {
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| format!("Error parsing {}: {}", p.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", p.display(), e)),
+        }
+    }
+
+    /// The registry called `name`, or the one at `name` if it's a URL
+    fn lookup(&self, name: &str) -> Result<Target, String> {
+        if name.contains("://") {
+            let url = name.trim_end_matches('/');
+            let token = self
+                .registries
+                .values()
+                .find(|r| r.url.trim_end_matches('/') == url)
+                .and_then(|r| r.token.clone());
+            return Ok(Target {
+                url: name.to_owned(),
+                token,
+            });
+        }
+        match self.registries.get(name) {
+            Some(registry) => Ok(Target {
+                url: registry.url.clone(),
+                token: registry.token.clone(),
+            }),
+            None => Err(format!(
+                "No registry named \"{}\" in {}",
+                name,
+                config_file()?.display()
+            )),
+        }
+    }
+
+    /// The registry serving `@scope/...`, if one does
+    fn scoped(&self, scope: &str) -> Option<Target> {
+        let (_, registry) = self
+            .registries
+            .iter()
+            .find(|(_, r)| r.scopes.iter().any(|s| s == scope))
+            .or_else(|| self.registries.get_key_value(scope))?;
+        Some(Target {
+            url: registry.url.clone(),
+            token: registry.token.clone(),
+        })
+    }
+
+    fn default_registry(&self) -> Result<Target, String> {
+        match &self.default {
+            Some(name) => self.lookup(name),
+            None => Ok(Target {
+                url: DEFAULT_REGISTRY.to_owned(),
+                token: None,
+            }),
+        }
+    }
+}
+
+/// The `--registry` and `--token` given on the command line
+#[derive(Debug, Default)]
+pub struct Remote {
+    pub registry: Option<String>,
+    pub token: Option<String>,
+}
+
+impl Remote {
+    /// Pick `--registry` if it was given, then `fallback`
+    fn pick(
+        &self,
+        settings: &Settings,
+        fallback: impl FnOnce() -> Result<Target, String>,
+    ) -> Result<Target, String> {
+        let mut target = match &self.registry {
+            Some(name) => settings.lookup(name)?,
+            None => fallback()?,
+        };
+        if self.token.is_some() {
+            target.token = self.token.clone();
+        }
+        Ok(target)
+    }
+
+    /// The registry a template spec is fetched from, along with the
+    /// `owner/name@version` to ask it for
+    pub fn for_template(
+        &self,
+        settings: &Settings,
+        spec: &str,
+    ) -> Result<(Target, String), String> {
+        match parse_scope(spec) {
+            Some((scope, rest)) => {
+                let target = self.pick(settings, || match settings.scoped(scope) {
+                    Some(target) => Ok(target),
+                    None => settings.default_registry(),
+                })?;
+                Ok((target, format!("{}/{}", scope, rest)))
+            }
+            None => Ok((
+                self.pick(settings, || settings.default_registry())?,
+                spec.into(),
+            )),
+        }
+    }
+
+    /// The registry a template is published to. `scope` and `preferred`
+    /// are the scope and registry its bowl.toml gives.
+    pub fn for_publish(
+        &self,
+        settings: &Settings,
+        scope: Option<&str>,
+        preferred: Option<&str>,
+    ) -> Result<Target, String> {
+        self.pick(settings, || {
+            match (scope.and_then(|scope| settings.scoped(scope)), preferred) {
+                (Some(target), _) => Ok(target),
+                (None, Some(name)) => settings.lookup(name),
+                (None, None) => settings.default_registry(),
+            }
+        })
+    }
+
+    /// The registry used when no template says otherwise
+    pub fn for_default(&self, settings: &Settings) -> Result<Target, String> {
+        self.pick(settings, || settings.default_registry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        toml::from_str(
+            r#"
+            default = "public"
+
+            [registries.public]
+            url = "https://soup.example.com"
+
+            [registries.corp]
+            url = "https://soup.corp.example"
+            token = "corp-token"
+            scopes = ["corp-labs"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn target(url: &str, token: Option<&str>) -> Target {
+        Target {
+            url: url.into(),
+            token: token.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_template_routing() {
+        let settings = settings();
+        let corp = target("https://soup.corp.example", Some("corp-token"));
+        let public = target("https://soup.example.com", None);
+        let route = |remote: &Remote, spec| remote.for_template(&settings, spec).unwrap();
+
+        let remote = Remote::default();
+        assert_eq!(
+            route(&remote, "@corp/api@1.2"),
+            (corp.clone(), "corp/api@1.2".into())
+        );
+        assert_eq!(
+            route(&remote, "@corp-labs/api"),
+            (corp, "corp-labs/api".into())
+        );
+        // unknown scopes are owners on the default registry
+        assert_eq!(
+            route(&remote, "@jack/api"),
+            (public.clone(), "jack/api".into())
+        );
+        assert_eq!(route(&remote, "jack/api"), (public, "jack/api".into()));
+
+        // the command line wins over everything
+        let remote = Remote {
+            registry: Some("http://localhost:7878".into()),
+            token: Some("mine".into()),
+        };
+        let local = target("http://localhost:7878", Some("mine"));
+        assert_eq!(route(&remote, "@corp/api"), (local, "corp/api".into()));
+        let remote = Remote {
+            registry: Some("nowhere".into()),
+            token: None,
+        };
+        assert!(remote.for_template(&settings, "jack/api").is_err());
+    }
+
+    #[test]
+    fn test_publish_routing() {
+        let settings = settings();
+        let remote = Remote::default();
+        let corp = target("https://soup.corp.example", Some("corp-token"));
+        let public = target("https://soup.example.com", None);
+        let route = |scope, preferred| remote.for_publish(&settings, scope, preferred).unwrap();
+        assert_eq!(route(None, Some("corp")), corp);
+        assert_eq!(route(None, None), public);
+        // the scope decides before the registry in bowl.toml
+        assert_eq!(route(Some("corp-labs"), None), corp);
+        assert_eq!(route(Some("corp"), Some("public")), corp);
+        assert_eq!(route(Some("jack"), Some("corp")), corp);
+        assert_eq!(route(Some("jack"), None), public);
+        let builtin = target(DEFAULT_REGISTRY, None);
+        assert_eq!(remote.for_default(&Settings::default()).unwrap(), builtin);
+
+        let remote = Remote {
+            registry: Some("public".into()),
+            token: Some("mine".into()),
+        };
+        let public = target("https://soup.example.com", Some("mine"));
+        assert_eq!(
+            remote
+                .for_publish(&settings, Some("corp"), Some("corp"))
+                .unwrap(),
+            public
+        );
+
+        // a configured registry given by URL keeps its token
+        let remote = Remote {
+            registry: Some("https://soup.corp.example/".into()),
+            token: None,
+        };
+        let corp = target("https://soup.corp.example/", Some("corp-token"));
+        assert_eq!(remote.for_publish(&settings, None, None).unwrap(), corp);
+    }
+}
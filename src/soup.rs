@@ -0,0 +1,691 @@
+//! Soup, a registry that serves bowlfiles from a directory
+//!
+//! Every version of a template is kept at
+//! `<root>/<owner>/<name>/<version>.bowl`, and `template.json` next to
+//! them describes the template and its versions. It is rebuilt from the
+//! bowlfiles if it goes missing, so bowlfiles can be copied in by hand.
+//!
+//! Publishing needs a token. `<root>/tokens.toml` maps each owner to the
+//! token they publish with, and templates are published under the owner
+//! whose token was sent. A private registry needs a token for every
+//! request. The routes are documented in `registry`.
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use semver::Version;
+use serde::Serialize;
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::{
+    registry::{
+        ErrorBody, Published, SearchResults, TemplateInfo, TemplateSummary, VersionInfo,
+        MAX_BOWLFILE_LEN,
+    },
+    store::{check_name, parse_version, pick_version},
+    templates::{
+        bowlfile::parse_config,
+        config::parse_template_config,
+        digest::{digest, to_hex},
+        stream::BowlReader,
+        symbols::CURRENT_VERSION,
+        versions::{read_version, v0_1_0},
+    },
+};
+
+/// File in the root that maps owners to their tokens
+pub const TOKENS_FILE: &str = "tokens.toml";
+
+const TEMPLATE_FILE: &str = "template.json";
+
+/// Templates listed per page unless asked otherwise
+const PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+/// Requests answered at the same time
+const WORKERS: usize = 8;
+
+/// What a request is answered with
+#[derive(Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+impl Reply {
+    fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        Self {
+            status,
+            // serializing these types can't fail
+            body: serde_json::to_vec(value).unwrap(),
+            content_type: "application/json",
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(
+            status,
+            &ErrorBody {
+                error: message.into(),
+            },
+        )
+    }
+}
+
+/// Lets `?` turn failures reading the registry's directory into replies
+impl From<String> for Reply {
+    fn from(e: String) -> Self {
+        Self::error(500, e)
+    }
+}
+
+/// Decode a `application/x-www-form-urlencoded` value
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'+', _) => decoded.push(b' '),
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Seconds since the unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The name, version, source and description a bowlfile gives in its
+/// bowl.toml, checking every digest in it on the way
+fn describe(bowlfile: &[u8]) -> Result<(String, Version, String, String), String> {
+    if read_version(bowlfile)? != CURRENT_VERSION {
+        return Err(format!(
+            "Only bowlfiles of version {} can be published, pack it again with this bowl",
+            CURRENT_VERSION
+        ));
+    }
+    let verification = v0_1_0::verify(bowlfile)?;
+    let damaged = verification.entries.iter().any(|(_, d)| d.is_some());
+    if damaged || !verification.archive_intact {
+        return Err("The bowlfile is damaged, check it with `bowl verify`".into());
+    }
+
+    let content = BowlReader::new(bowlfile)?.read_config_file()?;
+    let config = parse_config(&content)?;
+    let template_config = parse_template_config(&content)?;
+    check_name(&config.template.name)?;
+    let version = parse_version(&config.template.version)?;
+    Ok((
+        config.template.name,
+        version,
+        config.template.source,
+        template_config.template.description,
+    ))
+}
+
+/// A registry serving the templates under `root`
+pub struct Soup {
+    root: PathBuf,
+    /// Token of each owner
+    tokens: BTreeMap<String, String>,
+    private: bool,
+    /// Held while publishing, so a version can't be published twice at once
+    publishing: Mutex<()>,
+    /// Held while a template.json is replaced
+    saving: Mutex<()>,
+}
+
+/// Lock `mutex`, even if a thread panicked while holding it. The locks
+/// only guard the files, which are always replaced in one go.
+fn lock(mutex: &Mutex<()>) -> MutexGuard<'_, ()> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Soup {
+    /// Serve the templates under `root`, which is made if it doesn't exist
+    pub fn open(root: &Path, private: bool) -> Result<Self, String> {
+        fs::create_dir_all(root).map_err(|e| format!("Failed to make directory: {}", e))?;
+        let p = root.join(TOKENS_FILE);
+        let tokens = match fs::read_to_string(&p) {
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| format!("Error parsing {}: {}", p.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", p.display(), e)),
+        };
+        Ok(Self {
+            root: root.to_owned(),
+            tokens,
+            private,
+            publishing: Mutex::new(()),
+            saving: Mutex::new(()),
+        })
+    }
+
+    /// Who a token belongs to
+    fn owner(&self, token: Option<&str>) -> Option<&str> {
+        let token = token?;
+        self.tokens
+            .iter()
+            .find(|(_, t)| t.as_str() == token)
+            .map(|(owner, _)| owner.as_str())
+    }
+
+    fn template_dir(&self, owner: &str, name: &str) -> PathBuf {
+        self.root.join(owner).join(name)
+    }
+
+    /// Read what's published of a template, or `None` if nothing is
+    fn info(&self, owner: &str, name: &str) -> Result<Option<TemplateInfo>, String> {
+        let dir = self.template_dir(owner, name);
+        let p = dir.join(TEMPLATE_FILE);
+        if p.exists() {
+            let content = fs::read_to_string(&p)
+                .map_err(|e| format!("Failed to read {}: {}", p.display(), e))?;
+            return serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| format!("{} is damaged: {}", p.display(), e));
+        }
+        if !dir.is_dir() {
+            return Ok(None);
+        }
+
+        // rebuilt from bowlfiles that were copied in by hand
+        let read_err = |e: io::Error| format!("Failed to read {}: {}", dir.display(), e);
+        let mut info = TemplateInfo {
+            owner: owner.to_owned(),
+            name: name.to_owned(),
+            source: String::new(),
+            description: String::new(),
+            versions: Vec::new(),
+        };
+        for file in fs::read_dir(&dir).map_err(read_err)? {
+            let p = file.map_err(read_err)?.path();
+            // downloads are found by the file name, so it has to be the version
+            let version = match p.extension().is_some_and(|ext| ext == "bowl") {
+                true => Version::parse(&p.file_stem().unwrap().to_string_lossy()),
+                false => continue,
+            };
+            let Ok(version) = version else {
+                continue;
+            };
+            let bowlfile = fs::read(&p).map_err(read_err)?;
+            let Ok((_, _, source, description)) = describe(&bowlfile) else {
+                continue;
+            };
+            let published_at = fs::metadata(&p)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            add_version(
+                &mut info,
+                VersionInfo {
+                    version,
+                    digest: to_hex(&digest(&bowlfile)),
+                    size: bowlfile.len() as u64,
+                    published_at,
+                },
+                source,
+                description,
+            );
+        }
+        if info.versions.is_empty() {
+            return Ok(None);
+        }
+        self.save_info(&info)?;
+        Ok(Some(info))
+    }
+
+    fn save_info(&self, info: &TemplateInfo) -> Result<(), String> {
+        let p = self
+            .template_dir(&info.owner, &info.name)
+            .join(TEMPLATE_FILE);
+        let write_err = |e: io::Error| format!("Failed to write {}: {}", p.display(), e);
+        let content = serde_json::to_string_pretty(info).map_err(|e| e.to_string())?;
+        // replaced in one go so a crash can't leave half of it
+        let partial = p.with_extension("json.partial");
+        let _saving = lock(&self.saving);
+        fs::write(&partial, content).map_err(write_err)?;
+        fs::rename(&partial, &p).map_err(write_err)
+    }
+
+    /// Every template published to the registry
+    fn templates(&self) -> Result<Vec<TemplateInfo>, String> {
+        let read_err = |e: io::Error| format!("Failed to read registry: {}", e);
+        let mut templates = Vec::new();
+        for owner in fs::read_dir(&self.root).map_err(read_err)? {
+            let owner = owner.map_err(read_err)?.path();
+            let owner_name = owner.file_name().unwrap().to_string_lossy().into_owned();
+            if !owner.is_dir() || check_name(&owner_name).is_err() {
+                continue;
+            }
+            for template in fs::read_dir(&owner).map_err(read_err)? {
+                let template = template.map_err(read_err)?.path();
+                let name = template.file_name().unwrap().to_string_lossy().into_owned();
+                if check_name(&name).is_err() {
+                    continue;
+                }
+                if let Some(info) = self.info(&owner_name, &name)? {
+                    templates.push(info);
+                }
+            }
+        }
+        Ok(templates)
+    }
+
+    /// List the templates matching `q` in their name or description,
+    /// most recently published first
+    fn search(&self, query: &str) -> Result<Reply, Reply> {
+        let mut q = String::new();
+        let mut page = 1;
+        let mut per_page = PER_PAGE;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let number = || {
+                decode(value)
+                    .parse::<usize>()
+                    .map_err(|_| Reply::error(400, format!("Invalid {} \"{}\"", key, value)))
+            };
+            match key {
+                "q" => q = decode(value).to_lowercase(),
+                "page" => page = number()?.max(1),
+                "per_page" => per_page = number()?.clamp(1, MAX_PER_PAGE),
+                _ => {}
+            }
+        }
+
+        let mut matches = self
+            .templates()?
+            .into_iter()
+            .filter(|t| {
+                let name = format!("{}/{}", t.owner, t.name).to_lowercase();
+                name.contains(&q) || t.description.to_lowercase().contains(&q)
+            })
+            .filter_map(|t| {
+                let versions = t
+                    .versions
+                    .iter()
+                    .map(|v| v.version.clone())
+                    .collect::<Vec<_>>();
+                let latest = pick_version(&versions, None).ok()?.clone();
+                let published_at = t.versions.iter().map(|v| v.published_at).max()?;
+                Some(TemplateSummary {
+                    owner: t.owner,
+                    name: t.name,
+                    version: latest,
+                    description: t.description,
+                    source: t.source,
+                    published_at,
+                })
+            })
+            .collect::<Vec<TemplateSummary>>();
+        matches.sort_by_key(|t| Reverse(t.published_at));
+
+        let total = matches.len();
+        let templates = matches
+            .into_iter()
+            .skip((page - 1).saturating_mul(per_page))
+            .take(per_page)
+            .collect();
+        Ok(Reply::json(
+            200,
+            &SearchResults {
+                templates,
+                page,
+                per_page,
+                total,
+            },
+        ))
+    }
+
+    fn template(&self, owner: &str, name: &str) -> Result<Reply, Reply> {
+        match self.info(owner, name)? {
+            Some(info) => Ok(Reply::json(200, &info)),
+            None => Err(Reply::error(
+                404,
+                format!("No template named {}/{}", owner, name),
+            )),
+        }
+    }
+
+    fn download(&self, owner: &str, name: &str, version: &str) -> Result<Reply, Reply> {
+        let version = Version::parse(version).map_err(|e| Reply::error(400, format!("{}", e)))?;
+        let p = self
+            .template_dir(owner, name)
+            .join(format!("{}.bowl", version));
+        match fs::read(&p) {
+            Ok(body) => Ok(Reply {
+                status: 200,
+                body,
+                content_type: "application/octet-stream",
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Reply::error(
+                404,
+                format!("{}/{} {} isn't published", owner, name, version),
+            )),
+            Err(e) => Err(Reply::error(500, format!("Failed to read bowlfile: {}", e))),
+        }
+    }
+
+    fn publish(&self, owner: &str, bowlfile: &[u8]) -> Result<Reply, Reply> {
+        let (name, version, source, description) =
+            describe(bowlfile).map_err(|e| Reply::error(400, e))?;
+        let _publishing = lock(&self.publishing);
+        let mut info = self.info(owner, &name)?.unwrap_or_else(|| TemplateInfo {
+            owner: owner.to_owned(),
+            name: name.clone(),
+            source: String::new(),
+            description: String::new(),
+            versions: Vec::new(),
+        });
+        if info.versions.iter().any(|v| v.version == version) {
+            return Err(Reply::error(
+                409,
+                format!(
+                    "{}/{} {} is already published, bump the version in bowl.toml",
+                    owner, name, version
+                ),
+            ));
+        }
+
+        let dir = self.template_dir(owner, &name);
+        let write_err = |e: io::Error| format!("Failed to save bowlfile: {}", e);
+        fs::create_dir_all(&dir).map_err(write_err)?;
+        let p = dir.join(format!("{}.bowl", version));
+        let partial = p.with_extension("bowl.partial");
+        fs::write(&partial, bowlfile).map_err(write_err)?;
+        fs::rename(&partial, &p).map_err(write_err)?;
+
+        add_version(
+            &mut info,
+            VersionInfo {
+                version: version.clone(),
+                digest: to_hex(&digest(bowlfile)),
+                size: bowlfile.len() as u64,
+                published_at: now(),
+            },
+            source,
+            description,
+        );
+        self.save_info(&info)?;
+        Ok(Reply::json(
+            201,
+            &Published {
+                owner: owner.to_owned(),
+                name,
+                version,
+            },
+        ))
+    }
+
+    /// Answer a request for `url` sent with `token`
+    pub fn respond(&self, method: &str, url: &str, token: Option<&str>, body: &[u8]) -> Reply {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let owner = self.owner(token);
+        if self.private && owner.is_none() {
+            return Reply::error(401, "This registry needs a token");
+        }
+        let rest = path.trim_end_matches('/').strip_prefix("/api/templates");
+        let Some(rest) = rest.filter(|rest| rest.is_empty() || rest.starts_with('/')) else {
+            return Reply::error(404, format!("No route for {}", path));
+        };
+        let parts = rest.split('/').skip(1).collect::<Vec<&str>>();
+        if let Some(part) = parts.iter().take(2).find(|p| check_name(p).is_err()) {
+            return Reply::error(404, format!("No template named {}", part));
+        }
+
+        let reply = match (method, parts.as_slice()) {
+            ("GET", []) => self.search(query),
+            ("POST", []) => match owner {
+                Some(owner) => self.publish(owner, body),
+                None => Err(Reply::error(401, "Publishing needs a valid token")),
+            },
+            ("GET", [owner, name]) => self.template(owner, name),
+            ("GET", [owner, name, version]) => self.download(owner, name, version),
+            (_, [] | [_, _] | [_, _, _]) => Err(Reply::error(
+                405,
+                format!("{} isn't allowed for {}", method, path),
+            )),
+            _ => Err(Reply::error(404, format!("No route for {}", path))),
+        };
+        reply.unwrap_or_else(|e| e)
+    }
+
+    /// Answer every request made to `server`, a few at a time
+    pub fn serve(&self, server: &Server) {
+        thread::scope(|scope| {
+            for _ in 0..WORKERS {
+                scope.spawn(|| {
+                    for request in server.incoming_requests() {
+                        self.answer(request);
+                    }
+                });
+            }
+        });
+    }
+
+    fn answer(&self, mut request: Request) {
+        let reply = self.handle(&mut request);
+        println!("{} {} {}", request.method(), request.url(), reply.status);
+        let content_type = Header::from_bytes("Content-Type", reply.content_type).unwrap();
+        let response = Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(content_type);
+        if let Err(e) = request.respond(response) {
+            println!("Failed to answer request: {}", e);
+        }
+    }
+
+    fn handle(&self, request: &mut Request) -> Reply {
+        let token = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+            .map(str::to_owned);
+        let method = request.method().as_str().to_owned();
+        // only publishing sends a body, and one that's too big is refused
+        // before any of it is read
+        let mut body = Vec::new();
+        if method == "POST" {
+            if request
+                .body_length()
+                .is_some_and(|len| len as u64 > MAX_BOWLFILE_LEN)
+            {
+                return Reply::error(413, "The bowlfile is too big");
+            }
+            if let Err(e) = request
+                .as_reader()
+                .take(MAX_BOWLFILE_LEN + 1)
+                .read_to_end(&mut body)
+            {
+                return Reply::error(400, format!("Failed to read request: {}", e));
+            }
+            if body.len() as u64 > MAX_BOWLFILE_LEN {
+                return Reply::error(413, "The bowlfile is too big");
+            }
+        }
+        self.respond(&method, request.url(), token.as_deref(), &body)
+    }
+}
+
+/// Add a version to a template, taking its source and description if it's
+/// the newest
+fn add_version(info: &mut TemplateInfo, version: VersionInfo, source: String, description: String) {
+    if info.versions.iter().all(|v| v.version < version.version) {
+        info.source = source;
+        info.description = description;
+    }
+    info.versions.push(version);
+    info.versions.sort_by(|a, b| a.version.cmp(&b.version));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        registry::Registry,
+        templates::{compression::Compression, files::FileContent, stream::BowlWriter},
+    };
+    use std::{env, io::Write, net::TcpStream, thread, time::Duration};
+
+    fn bowlfile(name: &str, version: &str) -> Vec<u8> {
+        let config = format!(
+            "[template]\nname = \"{}\"\nversion = \"{}\"\nsource = \"https://example.com\"\n\
+             description = \"Serves {}\"\n\n[options]\n",
+            name, version, name
+        );
+        let mut writer = BowlWriter::new(Vec::new(), Compression::Zstd).unwrap();
+        for file in [
+            FileContent::new("./bowl.toml".into(), config.into_bytes()),
+            FileContent::new("./main.go".into(), b"package main".to_vec()),
+        ] {
+            writer.write_file(&file).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    /// A registry in a fresh directory where jack's token is "secret"
+    fn soup(test: &str, private: bool) -> Soup {
+        let root = env::temp_dir().join(format!("bowl-soup-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(TOKENS_FILE), "jack = \"secret\"\n").unwrap();
+        Soup::open(&root, private).unwrap()
+    }
+
+    #[test]
+    fn test_publish_and_fetch() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let soup = soup("publish", false);
+        thread::spawn(move || soup.serve(&server));
+
+        let publish = |registry: &Registry, version| {
+            let bowlfile = bowlfile("api", version);
+            registry.publish(&bowlfile[..], bowlfile.len() as u64)
+        };
+        let registry = Registry::new(&url, Some("secret".into()));
+        for version in ["1.0.0", "1.1.0"] {
+            let published = publish(&registry, version).unwrap();
+            assert_eq!(published.owner, "jack");
+        }
+        let err = publish(&registry, "1.1.0").unwrap_err();
+        assert!(err.contains("409"), "{}", err);
+        let err = publish(&Registry::new(&url, Some("wrong".into())), "2.0.0").unwrap_err();
+        assert!(err.contains("401"), "{}", err);
+
+        let info = registry.template("jack", "api").unwrap();
+        assert_eq!(info.description, "Serves api");
+        assert_eq!(info.versions.len(), 2);
+        let mut downloaded = Vec::new();
+        registry
+            .download("jack", "api", &Version::new(1, 0, 0))
+            .unwrap()
+            .read_to_end(&mut downloaded)
+            .unwrap();
+        assert_eq!(downloaded, bowlfile("api", "1.0.0"));
+        assert_eq!(to_hex(&digest(&downloaded)), info.versions[0].digest);
+        assert!(registry.template("jack", "web").is_err());
+    }
+
+    #[test]
+    fn test_slow_upload_blocks_nobody() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let soup = soup("slow", false);
+        thread::spawn(move || soup.serve(&server));
+
+        // an upload that never finishes sending its body
+        let mut slow = TcpStream::connect(addr).unwrap();
+        slow.write_all(
+            b"POST /api/templates HTTP/1.1\r\nHost: soup\r\n\
+            Authorization: Bearer secret\r\nContent-Length: 100000\r\n\r\nBOWL",
+        )
+        .unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let results = Registry::new(&format!("http://{}", addr), None)
+            .search("", 1, 5)
+            .unwrap();
+        assert_eq!(results.unwrap().total, 0);
+        drop(slow);
+    }
+
+    #[test]
+    fn test_search_pages() {
+        let soup = soup("search", false);
+        for name in ["api", "web", "cli"] {
+            let reply = soup.respond(
+                "POST",
+                "/api/templates",
+                Some("secret"),
+                &bowlfile(name, "1.0.0"),
+            );
+            assert_eq!(reply.status, 201);
+        }
+        let search = |url: &str| {
+            let reply = soup.respond("GET", url, None, &[]);
+            serde_json::from_slice::<SearchResults>(&reply.body).unwrap()
+        };
+
+        let results = search("/api/templates?per_page=2");
+        assert_eq!((results.total, results.templates.len()), (3, 2));
+        assert_eq!(
+            search("/api/templates?per_page=2&page=2").templates.len(),
+            1
+        );
+        let results = search("/api/templates?q=Serves+w");
+        assert_eq!(results.templates[0].name, "web");
+        assert_eq!(results.templates[0].version, Version::new(1, 0, 0));
+        assert_eq!(search("/api/templates?q=nothing").total, 0);
+    }
+
+    #[test]
+    fn test_reject_bad_requests() {
+        let soup = soup("reject", true);
+        assert_eq!(soup.respond("GET", "/api/templates", None, &[]).status, 401);
+        let get = |url: &str| soup.respond("GET", url, Some("secret"), &[]).status;
+        assert_eq!(get("/api/templates/jack/..%2F..%2Fetc"), 404);
+        assert_eq!(get("/api/templates/jack/../tokens.toml"), 404);
+        assert_eq!(get("/api/templates/jack/api/nope"), 400);
+        assert_eq!(get("/elsewhere"), 404);
+        assert_eq!(get("/api/templatesXYZ"), 404);
+        let reply = soup.respond("POST", "/api/templatesXYZ", Some("secret"), &[]);
+        assert_eq!(reply.status, 404);
+
+        let mut damaged = bowlfile("api", "1.0.0");
+        let pos = damaged.len() / 2;
+        damaged[pos] ^= 0xff;
+        let reply = soup.respond("POST", "/api/templates", Some("secret"), &damaged);
+        assert_eq!(reply.status, 400);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("axum+askama%2Fweb"), "axum askama/web");
+        assert_eq!(decode("100%"), "100%");
+    }
+}
@@ -0,0 +1,394 @@
+//! Templates saved on this machine
+//!
+//! Every version of a template is kept as its own bowlfile at
+//! `<data dir>/templates/<name>/<version>.bowl`, so saving a new version
+//! leaves the older ones usable. `templates/index.json` records what is
+//! saved, and is rebuilt from the bowlfiles if it goes missing or no
+//! longer matches them.
+//!
+//! Templates are asked for as `name`, `name@latest`, `name@1.2` (the
+//! newest 1.2.x) or any semver requirement like `name@^1.2`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    files::{data_dir, templates_dir},
+    templates::{
+        digest::{to_hex, HashingReader},
+        stream::BowlReader,
+    },
+};
+
+const INDEX_FILE: &str = "index.json";
+
+/// Make sure `name` can be used as a directory of the store
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    match valid {
+        true => Ok(()),
+        false => Err(format!(
+            "Invalid template name \"{}\": only letters, digits, '-', '_' and '.' are allowed",
+            name
+        )),
+    }
+}
+
+/// Parse the version of a template, which has to be semver
+pub fn parse_version(version: &str) -> Result<Version, String> {
+    Version::parse(version).map_err(|e| {
+        format!(
+            "Invalid template version \"{}\": {} (expected something like 1.0.0)",
+            version, e
+        )
+    })
+}
+
+/// Split `name@version` into its name and the version asked for, if any
+pub fn parse_spec(spec: &str) -> (&str, Option<&str>) {
+    // the first character is skipped so scoped names can start with @
+    match spec.char_indices().skip(1).find(|(_, c)| *c == '@') {
+        Some((i, _)) => (&spec[..i], Some(&spec[i + 1..])),
+        None => (spec, None),
+    }
+}
+
+/// Which versions of a template are asked for. `None` means the latest.
+fn parse_requirement(version: Option<&str>) -> Result<Option<VersionReq>, String> {
+    let requirement = match version {
+        None | Some("latest") => return Ok(None),
+        // a bare version like 1.2 means exactly 1.2.x, not ^1.2
+        Some(v) if v.chars().all(|c| c.is_ascii_digit() || c == '.') => format!("={}", v),
+        Some(v) => v.to_owned(),
+    };
+    VersionReq::parse(&requirement)
+        .map(Some)
+        .map_err(|e| format!("Invalid version \"{}\": {}", version.unwrap(), e))
+}
+
+/// Where a version of a template is kept
+pub fn version_path(name: &str, version: &Version) -> Result<PathBuf, String> {
+    check_name(name)?;
+    Ok(templates_dir()?
+        .join(name)
+        .join(format!("{}.bowl", version)))
+}
+
+/// SHA-256 of a whole bowlfile, as hex
+pub fn file_digest(path: &Path) -> Result<String, String> {
+    let read_err = |e: io::Error| format!("Failed to read {}: {}", path.display(), e);
+    let mut hashing = HashingReader::new(File::open(path).map_err(read_err)?);
+    io::copy(&mut hashing, &mut io::sink()).map_err(read_err)?;
+    Ok(to_hex(&hashing.finalize()))
+}
+
+/// Seconds since the unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// One saved version of a template
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: Version,
+    /// SHA-256 of the bowlfile, as hex
+    pub digest: String,
+    /// Seconds since the unix epoch
+    pub saved_at: u64,
+    /// The `source` of the template from its bowl.toml
+    pub source: String,
+}
+
+impl IndexEntry {
+    /// Where the bowlfile of this entry is kept
+    pub fn path(&self) -> Result<PathBuf, String> {
+        version_path(&self.name, &self.version)
+    }
+}
+
+/// A bowlfile found in the store
+struct SavedFile {
+    name: String,
+    version: Version,
+    path: PathBuf,
+    /// Seconds since the unix epoch
+    modified: u64,
+}
+
+impl SavedFile {
+    /// Index entry for the bowlfile, read from the file itself
+    fn entry(self) -> Result<IndexEntry, String> {
+        let file =
+            File::open(&self.path).map_err(|e| format!("Failed to read local store: {}", e))?;
+        let source = BowlReader::new(BufReader::new(file))
+            .and_then(|mut reader| reader.read_config())
+            .map(|config| config.template.source)
+            .unwrap_or_default();
+        Ok(IndexEntry {
+            digest: file_digest(&self.path)?,
+            name: self.name,
+            version: self.version,
+            saved_at: self.modified,
+            source,
+        })
+    }
+}
+
+/// Every bowlfile in the store
+fn saved_files() -> Result<Vec<SavedFile>, String> {
+    let read_err = |e: io::Error| format!("Failed to read local store: {}", e);
+    let mut saved = Vec::new();
+    for dir in fs::read_dir(templates_dir()?).map_err(read_err)? {
+        let dir = dir.map_err(read_err)?.path();
+        let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        if !dir.is_dir() || check_name(&name).is_err() {
+            continue;
+        }
+        for file in fs::read_dir(&dir).map_err(read_err)? {
+            let p = file.map_err(read_err)?.path();
+            let version = match p.extension().is_some_and(|ext| ext == "bowl") {
+                true => Version::parse(&p.file_stem().unwrap().to_string_lossy()),
+                false => continue,
+            };
+            let Ok(version) = version else {
+                continue;
+            };
+            let modified = fs::metadata(&p)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            saved.push(SavedFile {
+                name: name.clone(),
+                version,
+                path: p,
+                modified,
+            });
+        }
+    }
+    Ok(saved)
+}
+
+/// Everything saved in the local store
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Index {
+    pub templates: Vec<IndexEntry>,
+}
+
+impl Index {
+    fn path() -> Result<PathBuf, String> {
+        Ok(templates_dir()?.join(INDEX_FILE))
+    }
+
+    /// Read the index, rebuilding it if it doesn't exist yet and bringing
+    /// it in line with the bowlfiles in the store otherwise
+    pub fn load() -> Result<Self, String> {
+        let p = Self::path()?;
+        if !p.exists() {
+            let mut index = Self::default();
+            index.reconcile()?;
+            index.save()?;
+            return Ok(index);
+        }
+        let content =
+            fs::read_to_string(&p).map_err(|e| format!("Failed to read store index: {}", e))?;
+        let mut index: Self = serde_json::from_str(&content).map_err(|e| {
+            format!(
+                "Store index {} is damaged: {}\nDelete it to have it rebuilt",
+                p.display(),
+                e
+            )
+        })?;
+        if index.reconcile()? {
+            index.save()?;
+        }
+        Ok(index)
+    }
+
+    /// Index bowlfiles the index doesn't know about or that changed since
+    /// they were recorded, and forget ones that are gone. A save that was
+    /// cut short after its bowlfile was written is picked up this way.
+    /// Returns whether anything changed.
+    fn reconcile(&mut self) -> Result<bool, String> {
+        let saved = saved_files()?;
+        let before = self.templates.len();
+        self.templates.retain(|e| {
+            saved
+                .iter()
+                .any(|f| f.name == e.name && f.version == e.version)
+        });
+        let mut changed = self.templates.len() != before;
+        for file in saved {
+            let current = self.templates.iter().any(|e| {
+                e.name == file.name && e.version == file.version && e.saved_at >= file.modified
+            });
+            if !current {
+                self.add(file.entry()?);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Write the index back to the store
+    pub fn save(&self) -> Result<(), String> {
+        let p = Self::path()?;
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to write store index: {}", e))?;
+        // replaced in one go so a crash can't leave half an index
+        let partial = p.with_extension("json.partial");
+        fs::write(&partial, content).map_err(|e| format!("Failed to write store index: {}", e))?;
+        fs::rename(&partial, &p).map_err(|e| format!("Failed to write store index: {}", e))
+    }
+
+    /// Record a saved version, replacing an earlier save of it
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.templates
+            .retain(|e| e.name != entry.name || e.version != entry.version);
+        self.templates.push(entry);
+        self.templates
+            .sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    }
+
+    /// Forget a saved version
+    pub fn remove(&mut self, name: &str, version: &Version) {
+        self.templates
+            .retain(|e| e.name != name || e.version != *version);
+    }
+
+    /// Saved versions of a template, oldest first
+    pub fn versions(&self, name: &str) -> impl Iterator<Item = &IndexEntry> {
+        let name = name.to_owned();
+        self.templates.iter().filter(move |e| e.name == name)
+    }
+
+    /// Find the version of a template asked for by `spec`
+    pub fn resolve(&self, spec: &str) -> Result<&IndexEntry, String> {
+        let (name, version) = parse_spec(spec);
+        let saved = self.versions(name).collect::<Vec<&IndexEntry>>();
+        if saved.is_empty() {
+            return Err(format!("No template named \"{}\" is saved locally", name));
+        }
+        let versions = saved
+            .iter()
+            .map(|e| e.version.clone())
+            .collect::<Vec<Version>>();
+        let picked = pick_version(&versions, version)
+            .map_err(|e| format!("No saved version of {} {}", name, e))?;
+        Ok(saved.into_iter().find(|e| e.version == *picked).unwrap())
+    }
+}
+
+/// Pick the version asked for out of `versions`, which are sorted oldest
+/// first. The latest is the newest release, or the newest pre-release if
+/// there are none.
+pub fn pick_version<'a>(
+    versions: &'a [Version],
+    requested: Option<&str>,
+) -> Result<&'a Version, String> {
+    let found = match parse_requirement(requested)? {
+        Some(requirement) => versions.iter().rev().find(|v| requirement.matches(v)),
+        None => versions
+            .iter()
+            .rev()
+            .find(|v| v.pre.is_empty())
+            .or(versions.last()),
+    };
+    found.ok_or_else(|| {
+        let versions = versions
+            .iter()
+            .map(Version::to_string)
+            .collect::<Vec<String>>();
+        format!(
+            "matches {}, the versions there are: {}",
+            requested.unwrap_or("latest"),
+            versions.join(", ")
+        )
+    })
+}
+
+/// Record a bowlfile that was just saved at `path` in the index
+pub fn record(name: &str, version: &Version, source: &str, path: &Path) -> Result<(), String> {
+    let mut index = Index::load()?;
+    index.add(IndexEntry {
+        name: name.to_owned(),
+        version: version.clone(),
+        digest: file_digest(path)?,
+        saved_at: now(),
+        source: source.to_owned(),
+    });
+    index.save()
+}
+
+/// Open the version of a template asked for by `spec`. Bowlfiles saved
+/// before versions were kept apart are still found by their name.
+pub fn open_template(spec: &str) -> Result<File, String> {
+    let index = Index::load()?;
+    let p = match index.resolve(spec) {
+        Ok(entry) => entry.path()?,
+        Err(e) => {
+            let legacy = data_dir()?.join(format!("{}.bowl", spec));
+            if parse_spec(spec).1.is_some() || check_name(spec).is_err() || !legacy.exists() {
+                return Err(e);
+            }
+            legacy
+        }
+    };
+    File::open(p).map_err(|e| format!("Failed to read bowlfile: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_name() {
+        for name in ["go-api", "my_template", "web.v2"] {
+            assert!(check_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".hidden", "../evil", "a/b", "name@1.0"] {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_resolve_versions() {
+        let mut index = Index::default();
+        for version in ["1.0.0", "1.2.0", "1.2.3", "2.0.0-beta.1", "1.10.0"] {
+            index.add(IndexEntry {
+                name: "api".into(),
+                version: Version::parse(version).unwrap(),
+                digest: String::new(),
+                saved_at: 0,
+                source: String::new(),
+            });
+        }
+        let resolve = |spec| index.resolve(spec).map(|e| e.version.to_string());
+
+        assert_eq!(resolve("api").unwrap(), "1.10.0");
+        assert_eq!(resolve("api@latest").unwrap(), "1.10.0");
+        assert_eq!(resolve("api@1.2").unwrap(), "1.2.3");
+        assert_eq!(resolve("api@1.2.0").unwrap(), "1.2.0");
+        assert_eq!(resolve("api@1").unwrap(), "1.10.0");
+        assert_eq!(resolve("api@~1.2").unwrap(), "1.2.3");
+        assert_eq!(resolve("api@2.0.0-beta.1").unwrap(), "2.0.0-beta.1");
+        assert!(resolve("api@3").is_err());
+        assert!(resolve("web").is_err());
+
+        assert_eq!(parse_spec("@scope/api@1.2"), ("@scope/api", Some("1.2")));
+    }
+}
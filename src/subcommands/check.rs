@@ -1,6 +1,6 @@
 use std::{
-    fs::{self, DirEntry},
-    path::Path,
+    fs, io,
+    path::{Path, PathBuf},
 };
 
 use bowl_core::config::Config;
@@ -8,16 +8,22 @@ use bowl_core::config::Config;
 use clap::Parser;
 
 use crate::{
-    files::file_entries,
-    templates::{bowlfile::BowlFile, files::FileContent},
+    patterns::project_paths,
+    templates::{
+        compression::Compression,
+        config::{parse_template_config, TemplateConfig},
+        stream::BowlWriter,
+    },
 };
 
 /// Arguments provided to the check command
 #[derive(Parser, Debug)]
 pub struct CheckArgs {}
 
-/// Checks that a user's configuration is valid
-pub fn handle_check(_command: CheckArgs) -> Result<(), String> {
+/// Read and validate the template in the current directory, returning
+/// its config, template settings and the paths packed into its bowlfile,
+/// bowl.toml first
+pub fn check_template() -> Result<(Config, TemplateConfig, Vec<PathBuf>), String> {
     // check for bowl.toml file
     let contents = match fs::read_to_string("bowl.toml") {
         Ok(contents) => contents,
@@ -28,6 +34,8 @@ pub fn handle_check(_command: CheckArgs) -> Result<(), String> {
 
     let config: Config =
         toml::from_str(&contents).map_err(|e| format!("Error parsing bowl.toml: {}", e))?;
+    let template_config = parse_template_config(contents.as_bytes())?;
+    template_config.check()?;
 
     if !Path::new(&config.options.readme).exists() {
         return Err(format!(
@@ -37,26 +45,21 @@ pub fn handle_check(_command: CheckArgs) -> Result<(), String> {
         ));
     }
 
-    let files = fs::read_dir(".")
-        .map_err(|e| e.to_string())?
-        .map(|x| x.map_err(|e| e.to_string()))
-        .collect::<Result<Vec<DirEntry>, String>>()?
-        .into_iter()
-        .map(file_entries)
-        .collect::<Result<Vec<Vec<DirEntry>>, String>>()?
-        .into_iter()
-        .flatten()
-        .map(|x| x.path())
-        .filter(|x| match config.options.ignore.clone() {
-            Some(ignore) => !ignore.contains(&x.to_str().unwrap().to_owned()),
-            None => true,
-        })
-        .map(FileContent::from_path)
-        .collect::<Result<Vec<FileContent>, String>>()?;
-
-    let bf = BowlFile::new(files);
-
-    let _ = bf.encode();
+    let mut paths = project_paths(&config, &template_config)?;
+    // bowl.toml goes first so it can be read before anything else
+    paths.sort_by_key(|p| p != Path::new("./bowl.toml"));
+    Ok((config, template_config, paths))
+}
+
+/// Checks that a user's configuration is valid
+pub fn handle_check(_command: CheckArgs) -> Result<(), String> {
+    let (_, _, paths) = check_template()?;
+
+    let mut writer = BowlWriter::new(io::sink(), Compression::None)?;
+    for p in paths {
+        writer.write_path(&p)?;
+    }
+    writer.finish()?;
 
     println!("Check succeeded!");
     Ok(())
@@ -0,0 +1,125 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Seek},
+};
+
+use clap::Parser;
+
+use super::list::saved_at;
+use crate::{
+    store::Index,
+    templates::{
+        bowlfile::parse_config,
+        config::{parse_template_config, relative_path},
+        digest::to_hex,
+        files::EntryKind,
+        signature::{read_signature, trusted_name},
+        stream::BowlReader,
+    },
+};
+
+/// Arguments to be passed when running info command
+#[derive(Parser, Debug)]
+pub struct InfoArgs {
+    /// Template to describe, as name or name@version
+    #[arg()]
+    pub template: String,
+}
+
+/// Print paths as an indented tree. Files come with their size, empty
+/// directories without one.
+fn print_tree(mut entries: Vec<(String, Option<u64>)>) {
+    entries.sort();
+    let mut open: Vec<&str> = Vec::new();
+    for (path, size) in &entries {
+        let parts = path.split('/').collect::<Vec<&str>>();
+        let (name, dirs) = parts.split_last().unwrap();
+        let common = open.iter().zip(dirs).take_while(|(a, b)| a == b).count();
+        open.truncate(common);
+        for dir in &dirs[common..] {
+            println!("  {}{}/", "  ".repeat(open.len()), dir);
+            open.push(dir);
+        }
+        let indent = "  ".repeat(open.len());
+        match size {
+            Some(size) => println!("  {}{} ({} bytes)", indent, name, size),
+            None => println!("  {}{}/", indent, name),
+        }
+    }
+}
+
+/// Show a saved template's metadata, readme and files without
+/// extracting it
+pub fn handle_info(cmd: InfoArgs) -> Result<(), String> {
+    let index = Index::load()?;
+    let entry = index.resolve(&cmd.template)?;
+    let mut file =
+        File::open(entry.path()?).map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+
+    let signer = match read_signature(&mut file)? {
+        Some(signature) => match trusted_name(&signature.public_key)? {
+            Some(name) => format!("trusted key \"{}\"", name),
+            None => format!("unknown key {}", to_hex(&signature.public_key)),
+        },
+        None => "nobody".into(),
+    };
+    file.rewind()
+        .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+    let mut reader = BowlReader::new(BufReader::new(file))?;
+    let config_file = reader.read_config_file()?;
+    let config = parse_config(&config_file)?;
+    let template_config = parse_template_config(&config_file)?;
+
+    println!("{} {}", config.template.name, config.template.version);
+    if !config.template.source.is_empty() {
+        println!("Source:    {}", config.template.source);
+    }
+    println!("Saved:     {}", saved_at(entry));
+    println!("Digest:    {}", entry.digest);
+    println!("Signed by: {}", signer);
+    if !template_config.variables.is_empty() {
+        let names = template_config
+            .variables
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect::<Vec<&str>>();
+        println!("Variables: {}", names.join(", "));
+    }
+    if !template_config.command.is_empty() {
+        let names = template_config
+            .command
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        println!("Commands:  {}", names.join(", "));
+    }
+
+    let readme_path = relative_path(&config.options.readme).to_owned();
+    let mut readme = None;
+    let mut entries = vec![("bowl.toml".to_owned(), Some(config_file.len() as u64))];
+    while let Some(entry) = reader.next_entry()? {
+        let path = relative_path(&entry.file_path).to_owned();
+        let size = match &entry.kind {
+            EntryKind::File if path == readme_path => {
+                let content = reader.read_content()?;
+                let size = content.len() as u64;
+                readme = Some(String::from_utf8_lossy(&content).into_owned());
+                Some(size)
+            }
+            EntryKind::File => Some(reader.copy_content(&mut io::sink())?),
+            EntryKind::Directory => None,
+            EntryKind::Symlink { target } => {
+                entries.push((format!("{} -> {}", path, target), Some(0)));
+                continue;
+            }
+        };
+        entries.push((path, size));
+    }
+
+    println!("\nFiles:");
+    print_tree(entries);
+    if let Some(readme) = readme {
+        println!("\n{}:\n{}", readme_path, readme.trim_end());
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use clap::Parser;
+
+use crate::templates::signature::generate_signing_key;
+
+/// Arguments to be passed when running keygen command
+#[derive(Parser, Debug)]
+pub struct KeygenArgs {
+    /// Where the signing key is written. The public key goes next to it
+    /// with a .pub extension.
+    #[arg()]
+    pub path: String,
+}
+
+fn write_key(path: &Path, hex: String, mode: u32) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    writeln!(file, "{}", hex).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Generate a key pair for signing bowlfiles
+pub fn handle_keygen(cmd: KeygenArgs) -> Result<(), String> {
+    let key = generate_signing_key()?;
+    let secret = Path::new(&cmd.path);
+    let public = secret.with_extension("pub");
+
+    write_key(secret, hex::encode(key.to_bytes()), 0o600)?;
+    write_key(&public, hex::encode(key.verifying_key().to_bytes()), 0o644)?;
+
+    println!("Signing key written to {}", secret.display());
+    println!("Public key written to {}", public.display());
+    println!("Share the public key so others can `bowl trust` your templates");
+
+    Ok(())
+}
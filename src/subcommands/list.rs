@@ -0,0 +1,59 @@
+use std::{
+    fs,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use clap::Parser;
+
+use crate::store::{Index, IndexEntry};
+
+/// Arguments to be passed when running list command
+#[derive(Parser, Debug)]
+pub struct ListArgs {
+    /// Only list the versions of this template
+    #[arg()]
+    pub name: Option<String>,
+}
+
+/// When an entry was saved, to the second
+pub fn saved_at(entry: &IndexEntry) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(entry.saved_at);
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// List the templates in the local store with their versions and sizes
+pub fn handle_list(cmd: ListArgs) -> Result<(), String> {
+    let index = Index::load()?;
+    let entries = index
+        .templates
+        .iter()
+        .filter(|e| match &cmd.name {
+            Some(name) => e.name == *name,
+            None => true,
+        })
+        .collect::<Vec<&IndexEntry>>();
+    if entries.is_empty() {
+        return Err(match &cmd.name {
+            Some(name) => format!("No template named \"{}\" is saved locally", name),
+            None => "No templates are saved locally yet, save one with `bowl save`".into(),
+        });
+    }
+
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for entry in entries {
+        // a bowlfile deleted behind the store's back is still listed
+        let size = match fs::metadata(entry.path()?) {
+            Ok(metadata) => format!("{} bytes", metadata.len()),
+            Err(_) => "missing".into(),
+        };
+        println!(
+            "{:<width$}  {:<12} {:>16}  saved {}",
+            entry.name,
+            entry.version.to_string(),
+            size,
+            saved_at(entry),
+            width = width
+        );
+    }
+    Ok(())
+}
@@ -1,6 +1,14 @@
 pub mod check;
+pub mod info;
+pub mod keygen;
+pub mod list;
 pub mod new;
 pub mod publish;
+pub mod remove;
 pub mod run;
+pub mod save;
+pub mod search;
+pub mod trust;
 pub mod use_cmd;
+pub mod verify;
 
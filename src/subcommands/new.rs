@@ -5,6 +5,11 @@ use inquire::Text;
 
 use bowl_core::config::Config;
 
+use crate::templates::{
+    prompt::{Value, Variables},
+    render::render,
+};
+
 const MD_TEMPLATE: &str = include_str!("../../templates/bowl.md");
 
 /// Arguments to be passed when running new command
@@ -54,11 +59,8 @@ pub fn handle_new(cmd: NewArgs) -> Result<(), String> {
 
     // create md file
     let mut md = File::create(md_file).map_err(|e| e.to_string())?;
-    md.write(
-        MD_TEMPLATE
-            .replace("{{BOWL_NAME}}", &project_name)
-            .as_bytes(),
-    )
-    .map_err(|e| e.to_string())?;
+    let variables = Variables::from([("BOWL_NAME".into(), Value::Text(project_name))]);
+    md.write(render(MD_TEMPLATE, &variables)?.as_bytes())
+        .map_err(|e| e.to_string())?;
     Ok(())
 }
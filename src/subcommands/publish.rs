@@ -1,14 +1,17 @@
-use bowl_core::config::Config;
 use clap::Parser;
+use ed25519_dalek::SigningKey;
 use std::{
-    fs::{self, DirEntry, File},
-    io::Write,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Seek, Write},
     path::{Path, PathBuf},
 };
 
+use super::{check::check_template, save::save_template};
 use crate::{
-    files::{file_entries, save_file_locally},
-    templates::{bowlfile::BowlFile, files::FileContent},
+    registry::{Published, Registry},
+    settings::{Remote, Settings},
+    templates::{compression::Compression, signature::read_signing_key, stream::BowlWriter},
 };
 
 /// Arguments to be passed when running publish command
@@ -20,70 +23,126 @@ pub struct PublishArgs {
 
     #[arg(long, action)]
     pub local: bool,
-}
 
-pub fn handle_publish(cmd: PublishArgs) -> Result<(), String> {
-    // check for bowl.toml file
-    let contents = match fs::read_to_string("bowl.toml") {
-        Ok(contents) => contents,
-        Err(_) => {
-            return Err("Couldn't find bowl.toml".into());
-        }
-    };
+    /// Codec used to compress the files in the bowlfile
+    #[arg(long, value_enum, default_value_t = Compression::Zstd)]
+    pub compression: Compression,
 
-    let config: Config =
-        toml::from_str(&contents).map_err(|e| format!("Error parsing bowl.toml: {}", e))?;
+    /// Signing key to sign the bowlfile with (see `bowl keygen`)
+    #[arg(long)]
+    pub sign_key: Option<String>,
+}
 
-    if !Path::new(&config.options.readme).exists() {
-        return Err(format!(
-            "Error: ReadMe file \"{}\" not found\nThe path of this \
-                    readme file can be set with the \"readme\" option in bowl.toml",
-            config.options.readme
-        ));
+/// Pack `paths` into a bowlfile written to `out`
+pub fn pack<W: Write>(
+    out: W,
+    paths: &[PathBuf],
+    compression: Compression,
+    signing_key: Option<SigningKey>,
+) -> Result<(), String> {
+    let mut writer = BowlWriter::new(out, compression)?;
+    if let Some(key) = signing_key {
+        writer = writer.sign_with(key);
     }
+    for p in paths {
+        writer.write_path(p)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
 
-    let ignore = match &config.options.ignore {
-        Some(ignore) => ignore.iter().map(PathBuf::from).collect::<Vec<PathBuf>>(),
-        None => Vec::new(),
-    };
+/// Make a new temp file to pack an upload into. The name is random and
+/// the file mustn't exist yet, so nothing planted in a shared temp
+/// directory is followed or truncated.
+fn create_temp() -> Result<(PathBuf, File), String> {
+    let create_err = |e: io::Error| format!("Failed to create temp file: {}", e);
+    for _ in 0..8 {
+        let mut suffix = [0; 8];
+        getrandom::getrandom(&mut suffix)
+            .map_err(|e| format!("Failed to create temp file: {}", e))?;
+        let p = env::temp_dir().join(format!("bowl-publish-{}.bowl", hex::encode(suffix)));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&p)
+        {
+            Ok(file) => return Ok((p, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(create_err(e)),
+        }
+    }
+    Err(create_err(io::ErrorKind::AlreadyExists.into()))
+}
 
-    let files = fs::read_dir(".")
-        .map_err(|e| e.to_string())?
-        .map(|x| x.map_err(|e| e.to_string()))
-        .collect::<Result<Vec<DirEntry>, String>>()?
-        .into_iter()
-        .map(file_entries)
-        .collect::<Result<Vec<Vec<DirEntry>>, String>>()?
-        .into_iter()
-        .flatten()
-        .map(|x| x.path())
-        .filter(|x| !ignore.contains(x))
-        .map(FileContent::from_path)
-        .collect::<Result<Vec<FileContent>, String>>()?;
+/// Pack `paths` into the temp file `tmp` and upload it to `registry`
+fn upload(
+    registry: &Registry,
+    mut tmp: &File,
+    paths: &[PathBuf],
+    compression: Compression,
+    signing_key: Option<SigningKey>,
+) -> Result<Published, String> {
+    pack(BufWriter::new(tmp), paths, compression, signing_key)?;
+    let read_err = |e: io::Error| format!("Failed to read bowlfile: {}", e);
+    let len = tmp.stream_position().map_err(read_err)?;
+    tmp.rewind().map_err(read_err)?;
+    registry.publish(BufReader::new(tmp), len)
+}
 
-    let bf = BowlFile::new(files);
+pub fn handle_publish(cmd: PublishArgs, remote: &Remote) -> Result<(), String> {
+    let (config, template_config, paths) = check_template()?;
 
-    let bytes = bf.encode();
+    let signing_key = match &cmd.sign_key {
+        Some(path) => Some(read_signing_key(Path::new(path))?),
+        None => None,
+    };
 
-    if let Some(out) = cmd.output {
+    let (file, partial, p) = if let Some(out) = cmd.output {
         let p = PathBuf::from(&out);
         if let Some(parent) = p.parent() {
             fs::create_dir_all(parent).map_err(|e| format!("Failed to make directory: {}", e))?;
         }
-        // is director
         // if user provided a directory, save it as <name>.bowl in that dir
         // if the user provides a full filepath, save it at that path
-        let mut file = File::create(out).map_err(|e| format!("Error creating bowlfile: {}", e))?;
-
-        let _ = file
-            .write_all(&bytes)
-            .map_err(|e| format!("Failed to write bowlfile: {}", e));
+        // written next to it first so a failed build leaves the old one intact
+        let partial = PathBuf::from(format!("{}.partial", out));
+        let file = File::create(&partial).map_err(|e| format!("Error creating bowlfile: {}", e))?;
+        (file, partial, p)
     } else if cmd.local {
-        save_file_locally(format!("{}.bowl", config.template.name), bytes)?;
+        // kept for scripts written before `bowl save`, which it now is
+        let p = save_template(&config, &paths, cmd.compression, signing_key, true)?;
+        println!("Saved template to {}", p.display());
+        return Ok(());
     } else {
-        // TODO: should be pushed to soup registry (local install only for now)
-        todo!("Soup server not built yet")
+        let about = &template_config.template;
+        let registry = remote
+            .for_publish(
+                &Settings::load()?,
+                about.scope.as_deref(),
+                about.registry.as_deref(),
+            )?
+            .connect();
+        // packed to a temp file first since the upload needs its length
+        let (tmp, file) = create_temp()?;
+        let result = upload(&registry, &file, &paths, cmd.compression, signing_key);
+        let _ = fs::remove_file(&tmp);
+        let published = result?;
+        println!(
+            "Published {}/{} {} to {}",
+            published.owner,
+            published.name,
+            published.version,
+            registry.base_url()
+        );
+        return Ok(());
+    };
+
+    if let Err(e) = pack(BufWriter::new(file), &paths, cmd.compression, signing_key) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
     }
+    fs::rename(&partial, &p).map_err(|e| format!("Failed to save bowlfile: {}", e))?;
 
     println!("Bowl template built successfully!");
 
@@ -0,0 +1,61 @@
+use std::{fs, io::ErrorKind};
+
+use clap::Parser;
+
+use crate::store::{parse_spec, parse_version, Index, IndexEntry};
+
+/// Arguments to be passed when running remove command
+#[derive(Parser, Debug)]
+pub struct RemoveArgs {
+    /// Version to remove, as name@version
+    #[arg()]
+    pub template: String,
+
+    /// Remove every saved version of the template
+    #[arg(long, action)]
+    pub all: bool,
+}
+
+/// Delete saved versions of a template from the local store
+pub fn handle_remove(cmd: RemoveArgs) -> Result<(), String> {
+    let mut index = Index::load()?;
+    let (name, version) = parse_spec(&cmd.template);
+    let saved = index.versions(name).cloned().collect::<Vec<IndexEntry>>();
+    if saved.is_empty() {
+        return Err(format!("No template named \"{}\" is saved locally", name));
+    }
+
+    let removed = match (version, cmd.all) {
+        (Some(_), true) => return Err("Pass either a version or --all, not both".into()),
+        (None, false) => {
+            return Err(format!(
+                "Pass the version to remove, like {}@{}, or --all to remove every version",
+                name,
+                saved.last().unwrap().version
+            ))
+        }
+        (None, true) => saved,
+        (Some(version), false) => {
+            let version = parse_version(version)?;
+            match saved.into_iter().find(|e| e.version == version) {
+                Some(entry) => vec![entry],
+                None => return Err(format!("{} {} isn't saved locally", name, version)),
+            }
+        }
+    };
+
+    for entry in &removed {
+        let p = entry.path()?;
+        match fs::remove_file(&p) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(format!("Failed to remove {}: {}", p.display(), e))
+            }
+            _ => {}
+        }
+        // the directory only goes once its last version is gone
+        let _ = fs::remove_dir(p.parent().unwrap());
+        index.remove(&entry.name, &entry.version);
+        println!("Removed {} {}", entry.name, entry.version);
+    }
+    index.save()
+}
@@ -1,16 +1,231 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
 use clap::Parser;
 
+use crate::{
+    files::{check_ancestors, check_link, create_symlink, safe_join, scripts_cache_dir, set_mode},
+    store::open_template,
+    templates::{
+        bowlfile::parse_config,
+        command::{choose, Branch, Os, SCRIPTS_DIR},
+        config::{parse_template_config, relative_path},
+        digest::{to_hex, HashingReader},
+        files::EntryKind,
+        signature::trusted_signer,
+        stream::BowlReader,
+    },
+};
+
 /// Arguments to be passed when running run command
 #[derive(Parser, Debug)]
 pub struct RunArgs {
     #[arg()]
     pub template: String,
-    #[arg(value_delimiter=' ', num_args=1..)]
+    #[arg(value_delimiter=' ', num_args=1.., allow_hyphen_values = true)]
     pub cmd: Vec<String>,
+
+    /// Run the command even if the template isn't signed by a trusted key
+    #[arg(long, action)]
+    pub allow_unsigned: bool,
+
+    /// Show the branch and commands that would run without running them
+    #[arg(long, action)]
+    pub dry_run: bool,
+}
+
+/// Find an executable named `tool` in one of the PATH directories
+fn find_on_path(tool: &str) -> Option<PathBuf> {
+    let extensions = match env::var("PATHEXT") {
+        Ok(exts) if cfg!(windows) => exts.split(';').map(String::from).collect(),
+        _ => vec![String::new()],
+    };
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", tool, ext)))
+            .find(|p| p.is_file())
+    })
+}
+
+/// Run one command through the system shell
+fn exec(cmd: &str, dir: &Path) -> Result<(), String> {
+    println!("$ {}", cmd);
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(cmd)
+        .current_dir(dir)
+        .status()
+        .map_err(|e| format!("Failed to run `{}`: {}", cmd, e))?;
+    if !status.success() {
+        return Err(format!("`{}` failed with {}", cmd, status));
+    }
+    Ok(())
+}
+
+/// Extract the `bowl/` directory of a template into the scripts cache,
+/// unless this exact bowlfile was extracted before. `reader` has to be
+/// past the config.
+fn extract_scripts<R: Read>(reader: &mut BowlReader<R>, key: &str) -> Result<PathBuf, String> {
+    let dir = scripts_cache_dir()?.join(key);
+    if dir.exists() {
+        return Ok(dir);
+    }
+    // extracted somewhere else first so a failed run doesn't leave
+    // half a cache behind
+    let partial = dir.with_extension("partial");
+    if partial.exists() {
+        fs::remove_dir_all(&partial).map_err(|e| format!("Failed to clear cache: {}", e))?;
+    }
+    while let Some(entry) = reader.next_entry()? {
+        let relative = relative_path(&entry.file_path);
+        if relative != SCRIPTS_DIR && !relative.starts_with(&format!("{}/", SCRIPTS_DIR)) {
+            continue;
+        }
+        let p = &safe_join(&partial, relative)?;
+        if let EntryKind::Symlink { target } = &entry.kind {
+            check_link(relative, target)?;
+        }
+        check_ancestors(&partial, p)?;
+        if let Some(parent) = p.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to make directory: {}", e))?;
+        }
+        match &entry.kind {
+            EntryKind::File => {
+                let mut out = File::create(p).map_err(|e| format!("Error creating file: {}", e))?;
+                reader.copy_content(&mut out)?;
+                set_mode(p, entry.mode)?;
+            }
+            EntryKind::Directory => {
+                fs::create_dir_all(p).map_err(|e| format!("Failed to make directory: {}", e))?;
+                set_mode(p, entry.mode)?;
+            }
+            EntryKind::Symlink { target } => create_symlink(target, p)?,
+        }
+    }
+    fs::rename(&partial, &dir).map_err(|e| format!("Failed to fill cache: {}", e))?;
+    Ok(dir)
 }
 
 /// Run the user's specified command provided by the template
 pub fn handle_run(command: RunArgs) -> Result<(), String> {
-    println!("{} {}", command.cmd[0], command.cmd[1]);
-    todo!()
+    let mut file = open_template(&command.template)?;
+    if command.allow_unsigned {
+        println!("Warning: skipping signature check");
+    } else {
+        trusted_signer(&mut file)?;
+    }
+    file.rewind()
+        .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+    // cached scripts are kept per bowlfile so an update replaces them
+    let mut hashing = HashingReader::new(&mut file);
+    io::copy(&mut hashing, &mut io::sink())
+        .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+    let key = to_hex(&hashing.finalize());
+    file.rewind()
+        .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+
+    let mut reader = BowlReader::new(BufReader::new(file))?;
+    let config_file = reader.read_config_file()?;
+    let config = parse_config(&config_file)?;
+    let template_config = parse_template_config(&config_file)?;
+    template_config.check()?;
+
+    let (name, args) = command.cmd.split_first().ok_or("No command given")?;
+    let Some(template_command) = template_config.command.get(name) else {
+        let names = template_config
+            .command
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        return Err(match names.is_empty() {
+            true => format!("{} has no commands", config.template.name),
+            false => format!(
+                "{} has no command \"{}\", try one of: {}",
+                config.template.name,
+                name,
+                names.join(", ")
+            ),
+        });
+    };
+
+    let invocation = command.cmd.join(" ");
+    let (branches, rest) = template_command
+        .branches_for(args)
+        .map_err(|e| format!("Can't run `{}`: {}", invocation, e))?;
+
+    println!("Running `{}` from {}", invocation, config.template.name);
+    let choice = match choose(branches, Os::current(), |tool| find_on_path(tool).is_some()) {
+        Ok(choice) => choice,
+        Err(skipped) => {
+            let reasons = skipped
+                .iter()
+                .map(|(number, reason)| format!("  branch {} {}", number, reason))
+                .collect::<Vec<String>>();
+            return Err(format!(
+                "None of the branches of `{}` can run here:\n{}",
+                invocation,
+                reasons.join("\n")
+            ));
+        }
+    };
+    for (number, reason) in &choice.skipped {
+        println!("  branch {} skipped: {}", number, reason);
+    }
+    println!(
+        "  branch {} chosen ({})",
+        choice.number,
+        choice.branch.requirements()
+    );
+
+    if command.dry_run {
+        // scripts aren't extracted, but this is where they'd end up
+        let script = match &choice.branch.script {
+            Some(script) => Some(scripts_cache_dir()?.join(&key).join(script)),
+            None => None,
+        };
+        println!("Dry run, nothing will be run:");
+        if let Some(build) = script.as_ref().and_then(|s| choice.branch.build_command(s)) {
+            println!("$ {}", build);
+        }
+        for cmd in choice.branch.commands(script.as_deref(), rest) {
+            println!("$ {}", cmd);
+        }
+        return Ok(());
+    }
+
+    let script = match &choice.branch.script {
+        Some(script) => Some(extract_scripts(&mut reader, &key)?.join(script)),
+        None => None,
+    };
+    if let Some(script) = &script {
+        let program = Branch::program(script);
+        if let Some(build) = choice
+            .branch
+            .build_command(script)
+            .filter(|_| !program.exists())
+        {
+            exec(&build, script.parent().unwrap())?;
+        }
+    }
+
+    let dir = env::current_dir().map_err(|e| format!("Failed to read directory: {}", e))?;
+    for cmd in choice.branch.commands(script.as_deref(), rest) {
+        exec(&cmd, &dir)?;
+    }
+
+    Ok(())
 }
@@ -0,0 +1,80 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use bowl_core::config::Config;
+use clap::Parser;
+use ed25519_dalek::SigningKey;
+
+use super::{check::check_template, publish::pack};
+use crate::{
+    store::{parse_version, record, version_path},
+    templates::{compression::Compression, signature::read_signing_key},
+};
+
+/// Arguments to be passed when running save command
+#[derive(Parser, Debug)]
+pub struct SaveArgs {
+    /// Codec used to compress the files in the bowlfile
+    #[arg(long, value_enum, default_value_t = Compression::Zstd)]
+    pub compression: Compression,
+
+    /// Signing key to sign the bowlfile with (see `bowl keygen`)
+    #[arg(long)]
+    pub sign_key: Option<String>,
+
+    /// Replace the saved template if this version was saved before
+    #[arg(long, action)]
+    pub force: bool,
+}
+
+/// Pack the template into the local store under its name and version,
+/// returning where it was saved
+pub fn save_template(
+    config: &Config,
+    paths: &[PathBuf],
+    compression: Compression,
+    signing_key: Option<SigningKey>,
+    force: bool,
+) -> Result<PathBuf, String> {
+    let version = parse_version(&config.template.version)?;
+    let p = version_path(&config.template.name, &version)?;
+    if p.exists() && !force {
+        return Err(format!(
+            "{} {} is already saved\nBump the version in bowl.toml, or pass --force to replace it",
+            config.template.name, version
+        ));
+    }
+    fs::create_dir_all(p.parent().unwrap())
+        .map_err(|e| format!("Failed to make directory: {}", e))?;
+
+    // written next to it first so a failed save leaves the old one intact
+    let partial = p.with_extension("bowl.partial");
+    let file = File::create(&partial).map_err(|e| format!("Error creating bowlfile: {}", e))?;
+    if let Err(e) = pack(BufWriter::new(file), paths, compression, signing_key) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::rename(&partial, &p).map_err(|e| format!("Failed to save bowlfile: {}", e))?;
+    record(&config.template.name, &version, &config.template.source, &p)?;
+    Ok(p)
+}
+
+/// Validate the template in the current directory and save it to the
+/// local store
+pub fn handle_save(cmd: SaveArgs) -> Result<(), String> {
+    let (config, _, paths) = check_template()?;
+    let signing_key = match &cmd.sign_key {
+        Some(path) => Some(read_signing_key(Path::new(path))?),
+        None => None,
+    };
+
+    save_template(&config, &paths, cmd.compression, signing_key, cmd.force)?;
+    println!(
+        "Saved {} {} to the local store",
+        config.template.name, config.template.version
+    );
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use std::{cmp::Reverse, fs::File, io::BufReader};
+
+use clap::Parser;
+use semver::Version;
+
+use crate::{
+    registry::{SearchResults, TemplateSummary},
+    settings::{Remote, Settings},
+    store::{pick_version, Index, IndexEntry},
+    templates::{config::parse_template_config, stream::BowlReader},
+};
+
+/// Arguments to be passed when running search command
+#[derive(Parser, Debug)]
+pub struct SearchArgs {
+    /// Text to look for in template names and descriptions
+    #[arg()]
+    pub query: String,
+
+    /// Page of results to show, starting at 1
+    #[arg(long, default_value_t = 1)]
+    pub page: usize,
+
+    /// How many templates to show per page
+    #[arg(long, default_value_t = 20)]
+    pub per_page: usize,
+}
+
+/// The description in the bowl.toml of a saved template, if it has one
+fn description(entry: &IndexEntry) -> String {
+    entry
+        .path()
+        .and_then(|p| File::open(p).map_err(|e| e.to_string()))
+        .and_then(|file| BowlReader::new(BufReader::new(file))?.read_config_file())
+        .and_then(|content| parse_template_config(&content))
+        .map(|config| config.template.description)
+        .unwrap_or_default()
+}
+
+/// Search the latest saved version of every template in the local store,
+/// matching descriptions looked up with `describe`
+fn search_local(
+    index: &Index,
+    describe: impl Fn(&IndexEntry) -> String,
+    query: &str,
+    page: usize,
+    per_page: usize,
+) -> Result<SearchResults, String> {
+    let query = query.to_lowercase();
+    let mut names = index
+        .templates
+        .iter()
+        .map(|e| e.name.as_str())
+        .collect::<Vec<&str>>();
+    names.dedup();
+
+    let mut matches = Vec::new();
+    for name in names {
+        let saved = index.versions(name).collect::<Vec<&IndexEntry>>();
+        let versions = saved
+            .iter()
+            .map(|e| e.version.clone())
+            .collect::<Vec<Version>>();
+        let latest = pick_version(&versions, None)?;
+        let entry = saved.iter().find(|e| e.version == *latest).unwrap();
+        let description = describe(entry);
+        if !name.to_lowercase().contains(&query) && !description.to_lowercase().contains(&query) {
+            continue;
+        }
+        matches.push(TemplateSummary {
+            owner: String::new(),
+            name: name.to_owned(),
+            version: latest.clone(),
+            description,
+            source: entry.source.clone(),
+            published_at: entry.saved_at,
+        });
+    }
+    matches.sort_by_key(|t| Reverse(t.published_at));
+
+    let total = matches.len();
+    let templates = matches
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+    Ok(SearchResults {
+        templates,
+        page,
+        per_page,
+        total,
+    })
+}
+
+/// Search the registry for templates, or the local store when the
+/// registry can't be reached
+pub fn handle_search(cmd: SearchArgs, remote: &Remote) -> Result<(), String> {
+    if cmd.page == 0 || cmd.per_page == 0 {
+        return Err("--page and --per-page start at 1".into());
+    }
+    let registry = remote.for_default(&Settings::load()?)?.connect();
+    let results = match registry.search(&cmd.query, cmd.page, cmd.per_page)? {
+        Some(results) => results,
+        None => {
+            println!(
+                "Couldn't reach registry {}, searching saved templates instead",
+                registry.base_url()
+            );
+            let index = Index::load()?;
+            search_local(&index, description, &cmd.query, cmd.page, cmd.per_page)?
+        }
+    };
+    if results.total == 0 {
+        return Err(format!("No templates match \"{}\"", cmd.query));
+    }
+    if results.per_page == 0 {
+        return Err(format!(
+            "Invalid answer from registry {}: 0 templates per page",
+            registry.base_url()
+        ));
+    }
+
+    let names = results
+        .templates
+        .iter()
+        .map(|t| match t.owner.is_empty() {
+            true => t.name.clone(),
+            false => format!("{}/{}", t.owner, t.name),
+        })
+        .collect::<Vec<String>>();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+    for (name, template) in names.iter().zip(&results.templates) {
+        let line = format!(
+            "{:<width$}  {:<12} {}",
+            name,
+            template.version.to_string(),
+            template.description,
+            width = width
+        );
+        println!("{}", line.trim_end());
+        if !template.source.is_empty() {
+            println!("{:<width$}  {}", "", template.source, width = width);
+        }
+    }
+
+    let pages = results.total.div_ceil(results.per_page);
+    println!(
+        "\nPage {} of {} ({} templates)",
+        results.page, pages, results.total
+    );
+    if results.page < pages {
+        println!("See more with --page {}", results.page + 1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, version: &str, saved_at: u64) -> IndexEntry {
+        IndexEntry {
+            name: name.into(),
+            version: Version::parse(version).unwrap(),
+            digest: String::new(),
+            saved_at,
+            source: String::new(),
+        }
+    }
+
+    fn describe(entry: &IndexEntry) -> String {
+        match entry.name.as_str() {
+            "go-echo" => "Echo web server in Go".into(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn test_search_local() {
+        let mut index = Index::default();
+        for e in [
+            entry("axum-api", "1.0.0", 1),
+            entry("axum-api", "1.1.0", 4),
+            entry("axum-askama", "0.1.0", 3),
+            entry("go-echo", "2.0.0", 2),
+        ] {
+            index.add(e);
+        }
+
+        let results = search_local(&index, describe, "AXUM", 1, 1).unwrap();
+        assert_eq!((results.total, results.per_page), (2, 1));
+        assert_eq!(results.templates[0].name, "axum-api");
+        assert_eq!(results.templates[0].version, Version::new(1, 1, 0));
+        let results = search_local(&index, describe, "axum", 2, 1).unwrap();
+        assert_eq!(results.templates[0].name, "axum-askama");
+        assert!(search_local(&index, describe, "axum", 3, 1)
+            .unwrap()
+            .templates
+            .is_empty());
+
+        let total = |query| search_local(&index, describe, query, 1, 20).unwrap().total;
+        assert_eq!(total(""), 3);
+        assert_eq!(total("rails"), 0);
+
+        // descriptions match too
+        let results = search_local(&index, describe, "Web Server", 1, 20).unwrap();
+        assert_eq!(results.total, 1);
+        assert_eq!(results.templates[0].name, "go-echo");
+        assert_eq!(results.templates[0].description, "Echo web server in Go");
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+
+use crate::{
+    files::{trusted_key_paths, trusted_keys_dir},
+    store::check_name,
+    templates::signature::read_verifying_key,
+};
+
+/// Arguments to be passed when running trust command
+#[derive(Parser, Debug)]
+pub struct TrustArgs {
+    /// Public key of an author whose templates should be accepted.
+    /// Trusted keys are listed when left out.
+    #[arg()]
+    pub key: Option<String>,
+
+    /// Name to store the key under, defaults to the key's file name
+    #[arg(long)]
+    pub name: Option<String>,
+}
+
+/// Where the key trusted under `name` is kept in `dir`. Names may
+/// contain '.', so the extension is appended rather than set.
+fn key_file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.pub", name))
+}
+
+/// Add a public key to the trusted keys store
+pub fn handle_trust(cmd: TrustArgs) -> Result<(), String> {
+    let Some(key_path) = cmd.key else {
+        for p in trusted_key_paths()? {
+            let key = read_verifying_key(&p)?;
+            let name = p.file_stem().unwrap().to_string_lossy();
+            println!("{} {}", hex::encode(key.to_bytes()), name);
+        }
+        return Ok(());
+    };
+
+    let key_path = Path::new(&key_path);
+    let key = read_verifying_key(key_path)?;
+    let name = match cmd.name {
+        Some(name) => name,
+        None => key_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or("Couldn't name key, pass one with --name")?,
+    };
+    // the name becomes a file name in the trusted keys store
+    check_name(&name).map_err(|_| {
+        format!(
+            "Invalid key name \"{}\": only letters, digits, '-', '_' and '.' are allowed",
+            name
+        )
+    })?;
+
+    let p = key_file(&trusted_keys_dir()?, &name);
+    if p.exists() {
+        return Err(format!("A trusted key named \"{}\" already exists", name));
+    }
+    fs::write(&p, format!("{}\n", hex::encode(key.to_bytes())))
+        .map_err(|e| format!("Failed to save trusted key: {}", e))?;
+
+    println!("Templates signed by \"{}\" are now trusted", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_file_keeps_dotted_names() {
+        let p = key_file(Path::new("keys"), "web.v2");
+        assert_eq!(p, Path::new("keys/web.v2.pub"));
+        // trusted keys are named by their file stem when they're read back
+        assert_eq!(p.file_stem().unwrap(), "web.v2");
+        assert_ne!(p, key_file(Path::new("keys"), "web.v3"));
+    }
+}
@@ -1,12 +1,33 @@
 use std::{
+    collections::HashMap,
+    env,
     fs::{self, File},
-    io::Write,
-    path::Path,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
 };
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use globset::GlobSet;
+use inquire::Select;
 
-use crate::{files::get_file_locally, templates::bowlfile::BowlFile};
+use crate::{
+    files::{check_ancestors, check_link, create_symlink, safe_join, set_mode},
+    registry::fetch,
+    settings::{Remote, Settings},
+    store::open_template,
+    templates::{
+        bowlfile::parse_config,
+        command::SCRIPTS_DIR,
+        config::{glob_set, parse_template_config, relative_path, Conditions},
+        digest::{Digest, HashingWriter},
+        files::EntryKind,
+        merge::merge,
+        prompt::{collect, Value, Variables},
+        render::{is_binary, render},
+        signature::trusted_signer,
+        stream::{BowlReader, EntryHeader},
+    },
+};
 
 /// Arguments to be passed when running init command
 #[derive(Parser, Debug)]
@@ -14,6 +35,15 @@ pub struct UseArgs {
     #[arg()]
     pub template: String,
 
+    /// Directory to create the project in, made if it doesn't exist.
+    /// Defaults to the current directory.
+    #[arg()]
+    pub dest: Option<PathBuf>,
+
+    /// Generate into a destination directory that isn't empty
+    #[arg(long, action)]
+    pub force: bool,
+
     /// Specify the path of where the bowlfile should be sourced
     #[arg(long)]
     pub path: Option<String>,
@@ -21,36 +51,628 @@ pub struct UseArgs {
     /// Specify that the bowlfile should be found in the local store
     #[arg(long, action)]
     pub local: bool,
+
+    /// Use the template even if it isn't signed by a trusted key
+    #[arg(long, action)]
+    pub allow_unsigned: bool,
+
+    /// Answer a template variable up front instead of being asked,
+    /// as name=value
+    #[arg(long = "var", value_name = "NAME=VALUE")]
+    pub vars: Vec<String>,
+
+    /// List what would be written without writing anything
+    #[arg(long, action)]
+    pub dry_run: bool,
+
+    /// What to do with files that already exist
+    #[arg(long, value_enum, default_value_t = OnConflict::Abort)]
+    pub on_conflict: OnConflict,
 }
 
-/// create project from boilerplate code provided in
-/// the template argument
-pub fn handle_use(cmd: UseArgs) -> Result<(), String> {
-    let raw = if cmd.local {
-        get_file_locally(cmd.template)?
-    } else {
-        todo!("Soup registry not built yet")
-    };
+/// Decides where each entry of a template goes and what it contains
+struct Extractor {
+    readme: String,
+    variables: Variables,
+    render: GlobSet,
+    skip_render: GlobSet,
+    conditions: Conditions,
+    root: PathBuf,
+}
 
-    let bf = BowlFile::decode(raw)?;
+impl Extractor {
+    /// Where an entry is written, or `None` if it isn't part of the project
+    fn target(&self, entry: &EntryHeader) -> Result<Option<PathBuf>, String> {
+        if entry.file_path == self.readme || !self.conditions.includes(&entry.file_path) {
+            return Ok(None);
+        }
+        // scripts are only for `bowl run`
+        let relative = relative_path(&entry.file_path);
+        if relative == SCRIPTS_DIR || relative.starts_with(&format!("{}/", SCRIPTS_DIR)) {
+            return Ok(None);
+        }
+        // paths like cmd/{{ project_name }}/main.go are rendered too
+        let rendered = render(&entry.file_path, &self.variables)
+            .map_err(|e| format!("Failed to render path {}: {}", entry.file_path, e))?;
+        let path = safe_join(&self.root, &rendered)
+            .map_err(|e| format!("Invalid path for {}: {}", entry.file_path, e))?;
+        // a link out of the project would let later entries escape through it
+        if let EntryKind::Symlink { target } = &entry.kind {
+            check_link(&rendered, target)?;
+        }
+        // nor may anything be written through a link, including ones the
+        // template made itself, since this is checked again before writing
+        check_ancestors(&self.root, &path)?;
+        Ok(Some(path))
+    }
 
-    let config = bf.get_config()?;
+    /// Whether the answers are rendered into a file entry
+    fn renders(&self, entry: &EntryHeader) -> bool {
+        let path = relative_path(&entry.file_path);
+        self.render.is_match(path) && !self.skip_render.is_match(path)
+    }
+
+    /// Content of a file entry, with the variables rendered into it if
+    /// the template asks for that
+    fn content<R: Read>(
+        &self,
+        reader: &mut BowlReader<R>,
+        entry: &EntryHeader,
+    ) -> Result<Vec<u8>, String> {
+        let content = reader.read_content()?;
+        if !self.renders(entry) || is_binary(&content) {
+            return Ok(content);
+        }
+        render(std::str::from_utf8(&content).unwrap(), &self.variables)
+            .map(String::into_bytes)
+            .map_err(|e| format!("Failed to render {}: {}", entry.file_path, e))
+    }
 
-    for file in bf
-        .files
+    /// Write the content of a file entry to `out`. Only files that are
+    /// rendered are held in memory, everything else is streamed.
+    fn write_content<R: Read, W: Write>(
+        &self,
+        reader: &mut BowlReader<R>,
+        entry: &EntryHeader,
+        out: &mut W,
+    ) -> Result<(), String> {
+        if self.renders(entry) {
+            let content = self.content(reader, entry)?;
+            out.write_all(&content)
+                .map_err(|e| format!("Error writing {}: {}", entry.file_path, e))
+        } else {
+            reader.copy_content(out).map(|_| ())
+        }
+    }
+}
+
+/// Takes note of the size of content written to it and whether it is
+/// text, without keeping the content around
+#[derive(Default)]
+struct Probe {
+    len: u64,
+    binary: bool,
+    /// Start of a UTF-8 character cut off by the end of the last write
+    partial: Vec<u8>,
+}
+
+impl Probe {
+    /// Same as `is_binary` for the content written so far
+    fn is_binary(&self) -> bool {
+        self.binary || !self.partial.is_empty()
+    }
+}
+
+impl Write for Probe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let head = 8000u64.saturating_sub(self.len).min(buf.len() as u64) as usize;
+        self.binary |= buf[..head].contains(&0);
+        self.len += buf.len() as u64;
+        if !self.binary {
+            self.partial.extend_from_slice(buf);
+            match std::str::from_utf8(&self.partial) {
+                Ok(_) => self.partial.clear(),
+                Err(e) if e.error_len().is_none() => {
+                    self.partial.drain(..e.valid_up_to());
+                }
+                Err(_) => self.binary = true,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Digest of a file that already exists, along with a probe of it
+fn probe_file(path: &Path) -> Option<(Digest, Probe)> {
+    let mut file = File::open(path).ok()?;
+    let mut out = HashingWriter::new(Probe::default());
+    io::copy(&mut file, &mut out).ok()?;
+    Some((out.finalize(), out.into_inner()))
+}
+
+/// Read the bowlfile from the start, returning a reader positioned
+/// after the config along with the config's content
+fn read_from_start(file: &mut File) -> Result<(BowlReader<BufReader<&mut File>>, Vec<u8>), String> {
+    file.rewind()
+        .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+    let mut reader = BowlReader::new(BufReader::new(file))?;
+    // bowl.toml is always the first entry, so it's consumed here
+    let config_file = reader.read_config_file()?;
+    Ok((reader, config_file))
+}
+
+/// What to do with a file the template would write that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Stop before anything is written
+    Abort,
+    /// Keep the existing file
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Ask what to do for each file
+    Prompt,
+    /// Merge the template's version into the existing file
+    Merge,
+}
+
+/// What happens to one target of the template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Create,
+    /// Already exactly as the template would write it
+    Unchanged,
+    /// Exists with other content, not settled yet
+    Conflict,
+    Skip,
+    Overwrite,
+    Merge,
+}
+
+impl Action {
+    fn label(self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Unchanged => "unchanged",
+            Action::Conflict => "conflict",
+            Action::Skip => "skip",
+            Action::Overwrite => "overwrite",
+            Action::Merge => "merge",
+        }
+    }
+}
+
+/// A target found by the pre-flight scan
+struct Planned {
+    path: PathBuf,
+    detail: String,
+    directory: bool,
+    /// Only text files can be merged
+    mergeable: bool,
+    action: Action,
+}
+
+/// Look at every target before anything is written, so conflicts are
+/// known up front
+fn scan<R: Read>(
+    reader: &mut BowlReader<R>,
+    extractor: &Extractor,
+) -> Result<Vec<Planned>, String> {
+    let mut planned = Vec::new();
+    while let Some(entry) = reader.next_entry()? {
+        let Some(path) = extractor.target(&entry)? else {
+            continue;
+        };
+        let existing = path.symlink_metadata().ok();
+        let is_dir = existing.as_ref().is_some_and(|m| m.is_dir());
+        let kind_err = |what: &str| {
+            format!(
+                "{} already exists and isn't a {}, move it out of the way first",
+                path.display(),
+                what
+            )
+        };
+        let (detail, mergeable, action) = match &entry.kind {
+            EntryKind::File => {
+                let mut out = HashingWriter::new(Probe::default());
+                extractor.write_content(reader, &entry, &mut out)?;
+                let digest = out.finalize();
+                let content = out.into_inner();
+                let current = match &existing {
+                    Some(m) if m.is_file() => probe_file(&path),
+                    _ => None,
+                };
+                let action = match &current {
+                    None if existing.is_none() => Action::Create,
+                    _ if is_dir => return Err(kind_err("file")),
+                    Some((d, _)) if *d == digest => Action::Unchanged,
+                    _ => Action::Conflict,
+                };
+                let mergeable =
+                    !content.is_binary() && current.is_some_and(|(_, c)| !c.is_binary());
+                (format!("{} bytes", content.len), mergeable, action)
+            }
+            EntryKind::Directory => {
+                let action = match &existing {
+                    None => Action::Create,
+                    Some(_) if is_dir => Action::Unchanged,
+                    Some(_) => return Err(kind_err("directory")),
+                };
+                ("directory".into(), false, action)
+            }
+            EntryKind::Symlink { target } => {
+                let action = match &existing {
+                    None => Action::Create,
+                    Some(_) if is_dir => return Err(kind_err("link")),
+                    Some(m)
+                        if m.is_symlink()
+                            && fs::read_link(&path).is_ok_and(|t| t == Path::new(target)) =>
+                    {
+                        Action::Unchanged
+                    }
+                    Some(_) => Action::Conflict,
+                };
+                (format!("link to {}", target), false, action)
+            }
+        };
+        planned.push(Planned {
+            directory: entry.kind == EntryKind::Directory,
+            path,
+            detail,
+            mergeable,
+            action,
+        });
+    }
+    Ok(planned)
+}
+
+/// Ask what to do with one conflicting file
+fn ask(planned: &Planned) -> Result<Action, String> {
+    let mut options = vec!["skip", "overwrite"];
+    if planned.mergeable {
+        options.push("merge");
+    }
+    let message = format!("{} already exists", planned.path.display());
+    let answer = Select::new(&message, options)
+        .prompt()
+        .map_err(|e| format!("Failed to read answer: {}", e))?;
+    Ok(match answer {
+        "overwrite" => Action::Overwrite,
+        "merge" => Action::Merge,
+        _ => Action::Skip,
+    })
+}
+
+/// Settle every conflict found by the scan according to `policy`
+fn resolve(planned: &mut [Planned], policy: OnConflict) -> Result<(), String> {
+    let mut conflicts = planned
+        .iter_mut()
+        .filter(|p| p.action == Action::Conflict)
+        .peekable();
+    if conflicts.peek().is_none() {
+        return Ok(());
+    }
+    match policy {
+        OnConflict::Abort => {
+            let paths = conflicts
+                .map(|p| format!("  {}", p.path.display()))
+                .collect::<Vec<String>>();
+            Err(format!(
+                "These files already exist:\n{}\nNothing was written. Pass \
+                --on-conflict=skip|overwrite|merge|prompt to choose what happens to them",
+                paths.join("\n")
+            ))
+        }
+        OnConflict::Prompt => {
+            for p in conflicts {
+                p.action = ask(p)?;
+            }
+            Ok(())
+        }
+        OnConflict::Skip | OnConflict::Overwrite | OnConflict::Merge => {
+            for p in conflicts {
+                p.action = match policy {
+                    OnConflict::Overwrite => Action::Overwrite,
+                    OnConflict::Merge if p.mergeable => Action::Merge,
+                    // binaries and links are kept rather than mangled
+                    _ => Action::Skip,
+                };
+            }
+            Ok(())
+        }
+    }
+}
+
+/// List what every entry would become, without writing anything
+fn dry_run(planned: &mut [Planned], policy: OnConflict) -> Result<(), String> {
+    // asking or aborting only makes sense for a real run
+    if !matches!(policy, OnConflict::Abort | OnConflict::Prompt) {
+        resolve(planned, policy)?;
+    }
+    println!("Dry run, nothing will be written:");
+    for p in planned
         .iter()
-        .filter(|f| f.file_path != config.options.readme)
-        .filter(|f| f.file_path != "./bowl.toml")
+        .filter(|p| !(p.directory && p.action == Action::Unchanged))
     {
-        let p = Path::new(&file.file_path);
+        println!(
+            "  {:<9} {} ({})",
+            p.action.label(),
+            p.path.display(),
+            p.detail
+        );
+    }
+    let count = |action| planned.iter().filter(|p| p.action == action).count();
+    println!(
+        "{} to create, {} to overwrite, {} to merge, {} to skip, {} in conflict",
+        count(Action::Create),
+        count(Action::Overwrite),
+        count(Action::Merge),
+        count(Action::Skip),
+        count(Action::Conflict)
+    );
+    Ok(())
+}
+
+/// Write every entry of the template as the scan decided, returning how
+/// many conflicts were left in each merged file
+fn extract<R: Read>(
+    reader: &mut BowlReader<R>,
+    extractor: &Extractor,
+    planned: &[Planned],
+) -> Result<HashMap<PathBuf, usize>, String> {
+    let actions = planned
+        .iter()
+        .map(|p| (p.path.as_path(), p.action))
+        .collect::<HashMap<&Path, Action>>();
+    let mut merged = HashMap::new();
+    while let Some(entry) = reader.next_entry()? {
+        let Some(p) = extractor.target(&entry)? else {
+            continue;
+        };
+        let p = p.as_path();
+        let action = actions.get(p).copied().unwrap_or(Action::Create);
+        if !matches!(action, Action::Create | Action::Overwrite | Action::Merge) {
+            continue;
+        }
         if let Some(parent) = p.parent() {
             fs::create_dir_all(parent).map_err(|e| format!("Failed to make directory: {}", e))?;
         }
-        File::create(p)
-            .map_err(|e| format!("Error creating file: {}", e))?
-            .write_all(&file.content)
-            .map_err(|e| format!("Failed to write file: {}", e))?;
+        // writing through an existing link would change whatever it points at,
+        // and a link can't be made where a file is. Directories in the way
+        // were refused by the scan.
+        let is_link = matches!(entry.kind, EntryKind::Symlink { .. });
+        if action == Action::Overwrite
+            && p.symlink_metadata()
+                .is_ok_and(|m| m.is_symlink() || (is_link && !m.is_dir()))
+        {
+            fs::remove_file(p).map_err(|e| format!("Failed to replace {}: {}", p.display(), e))?;
+        }
+        match &entry.kind {
+            EntryKind::File if action == Action::Merge => {
+                let content = extractor.content(reader, &entry)?;
+                let existing = fs::read_to_string(p)
+                    .map_err(|e| format!("Failed to read {}: {}", p.display(), e))?;
+                let result = merge(&existing, &String::from_utf8_lossy(&content));
+                fs::write(p, result.content).map_err(|e| format!("Error writing file: {}", e))?;
+                merged.insert(p.to_path_buf(), result.conflicts);
+            }
+            EntryKind::File => {
+                let file = File::create(p).map_err(|e| format!("Error creating file: {}", e))?;
+                let mut out = BufWriter::new(file);
+                extractor.write_content(reader, &entry, &mut out)?;
+                out.flush()
+                    .map_err(|e| format!("Error writing {}: {}", p.display(), e))?;
+                set_mode(p, entry.mode)?;
+            }
+            EntryKind::Directory => {
+                fs::create_dir_all(p).map_err(|e| format!("Failed to make directory: {}", e))?;
+                set_mode(p, entry.mode)?;
+            }
+            EntryKind::Symlink { target } => create_symlink(target, p)?,
+        }
+    }
+    Ok(merged)
+}
+
+/// Report what happened to files that already existed
+fn summarize(planned: &[Planned], merged: &HashMap<PathBuf, usize>) {
+    let files = planned.iter().filter(|p| !p.directory);
+    let count = |action| files.clone().filter(|p| p.action == action).count();
+    println!(
+        "Created {}, overwrote {}, merged {}, skipped {}, {} already up to date",
+        count(Action::Create),
+        count(Action::Overwrite),
+        count(Action::Merge),
+        count(Action::Skip),
+        count(Action::Unchanged)
+    );
+    for p in files {
+        match p.action {
+            Action::Skip => println!("  skipped   {}", p.path.display()),
+            Action::Overwrite => println!("  overwrote {}", p.path.display()),
+            Action::Merge => match merged.get(&p.path) {
+                Some(0) | None => println!("  merged    {}", p.path.display()),
+                Some(n) => println!(
+                    "  merged    {} ({} conflicts to resolve)",
+                    p.path.display(),
+                    n
+                ),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Make sure the project can be generated into `dest`, returning the
+/// name of the directory
+fn check_dest(dest: &Path, force: bool) -> Result<String, String> {
+    let read_err = |e: std::io::Error| format!("Failed to read {}: {}", dest.display(), e);
+    if dest.exists() {
+        if !dest.is_dir() {
+            return Err(format!("{} is not a directory", dest.display()));
+        }
+        let empty = fs::read_dir(dest).map_err(read_err)?.next().is_none();
+        if !empty && !force {
+            return Err(format!(
+                "{} is not empty, pass --force to generate into it anyway",
+                dest.display()
+            ));
+        }
+    }
+    let dir = match fs::canonicalize(dest) {
+        Ok(dir) => dir,
+        Err(_) => env::current_dir().map_err(read_err)?.join(dest),
+    };
+    Ok(dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// create project from boilerplate code provided in
+/// the template argument
+pub fn handle_use(cmd: UseArgs, remote: &Remote) -> Result<(), String> {
+    let root = cmd.dest.clone().unwrap_or_else(|| PathBuf::from("."));
+    // files already in the current directory are left to --on-conflict
+    let name = check_dest(&root, cmd.force || cmd.dest.is_none())?;
+
+    let mut file = if cmd.local {
+        open_template(&cmd.template)?
+    } else {
+        let (target, spec) = remote.for_template(&Settings::load()?, &cmd.template)?;
+        fetch(&target.connect(), &spec)?
+    };
+
+    if cmd.allow_unsigned {
+        println!("Warning: skipping signature check");
+    } else {
+        let signer = trusted_signer(&mut file)?;
+        println!("Template signed by trusted key \"{}\"", signer);
     }
 
+    let (mut reader, config_file) = read_from_start(&mut file)?;
+    let config = parse_config(&config_file)?;
+    let template_config = parse_template_config(&config_file)?;
+    template_config.check()?;
+
+    // everything is asked before any file is written
+    let mut variables = collect(&template_config.variables, &cmd.vars)?;
+    if !variables.contains_key("BOWL_NAME") {
+        variables.insert("BOWL_NAME".into(), Value::Text(name));
+    }
+    let extractor = Extractor {
+        readme: config.options.readme,
+        render: glob_set(&template_config.render.include)?,
+        skip_render: glob_set(&template_config.render.skip)?,
+        conditions: template_config.conditions(&variables)?,
+        variables,
+        root,
+    };
+
+    let mut planned = scan(&mut reader, &extractor)?;
+    if cmd.dry_run {
+        return dry_run(&mut planned, cmd.on_conflict);
+    }
+    resolve(&mut planned, cmd.on_conflict)?;
+    fs::create_dir_all(&extractor.root)
+        .map_err(|e| format!("Failed to make {}: {}", extractor.root.display(), e))?;
+
+    let (mut reader, _) = read_from_start(&mut file)?;
+    let merged = extract(&mut reader, &extractor, &planned)?;
+    summarize(&planned, &merged);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::{
+        compression::Compression, config::TemplateConfig, files::FileContent, stream::BowlWriter,
+    };
+
+    fn link(path: &str, target: &str) -> FileContent {
+        FileContent {
+            kind: EntryKind::Symlink {
+                target: target.into(),
+            },
+            ..FileContent::new(path.into(), vec![])
+        }
+    }
+
+    fn extractor(root: &Path) -> Extractor {
+        let variables = Variables::new();
+        Extractor {
+            readme: "./README.md".into(),
+            render: GlobSet::empty(),
+            skip_render: GlobSet::empty(),
+            conditions: TemplateConfig::default().conditions(&variables).unwrap(),
+            variables,
+            root: root.to_owned(),
+        }
+    }
+
+    fn archive(files: &[FileContent]) -> Vec<u8> {
+        let mut writer = BowlWriter::new(Vec::new(), Compression::None).unwrap();
+        writer
+            .write_file(&FileContent::new("./bowl.toml".into(), vec![]))
+            .unwrap();
+        for f in files {
+            writer.write_file(f).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_overwrite_file_with_link() {
+        let root = env::temp_dir().join(format!("bowl-use-relink-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("current"), "old").unwrap();
+        let raw = archive(&[link("./current", "target")]);
+        let extractor = extractor(&root);
+
+        let mut reader = BowlReader::new(&raw[..]).unwrap();
+        reader.read_config_file().unwrap();
+        let mut planned = scan(&mut reader, &extractor).unwrap();
+        assert_eq!(planned[0].action, Action::Conflict);
+        resolve(&mut planned, OnConflict::Overwrite).unwrap();
+        let mut reader = BowlReader::new(&raw[..]).unwrap();
+        reader.read_config_file().unwrap();
+        let result = extract(&mut reader, &extractor, &planned);
+
+        let target = fs::read_link(root.join("current"));
+        fs::remove_dir_all(&root).unwrap();
+        result.unwrap();
+        assert_eq!(target.unwrap(), Path::new("target"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_refuses_link_chain() {
+        let root = env::temp_dir().join(format!("bowl-use-chain-{}", std::process::id()));
+        let project = root.join("p/q/project");
+        fs::create_dir_all(&project).unwrap();
+
+        // each link passes check_link on its own, but a/l/x really points
+        // two levels above the project since a/l is the project itself
+        let raw = archive(&[
+            link("./a/l", ".."),
+            link("./a/l/x", "../.."),
+            FileContent::new("./a/l/x/evil".into(), "pwned".into()),
+        ]);
+        let extractor = extractor(&project);
+        let mut reader = BowlReader::new(&raw[..]).unwrap();
+        reader.read_config_file().unwrap();
+        let err = extract(&mut reader, &extractor, &[]).unwrap_err();
+
+        let escaped = root.join("p/evil").exists() || project.join("x").exists();
+        fs::remove_dir_all(&root).unwrap();
+        assert!(err.contains("is a link"), "{}", err);
+        assert!(!escaped);
+    }
+}
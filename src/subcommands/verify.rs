@@ -0,0 +1,84 @@
+use std::fs;
+
+use clap::Parser;
+
+use crate::templates::{
+    digest::to_hex,
+    symbols::{CURRENT_VERSION, LEGACY_VERSION},
+    versions::{read_version, v0_1_0},
+};
+
+/// Arguments to be passed when running verify command
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    /// Path of the bowlfile to check
+    #[arg()]
+    pub file: String,
+}
+
+/// Check every checksum in a bowlfile and report the damaged entries
+pub fn handle_verify(cmd: VerifyArgs) -> Result<(), String> {
+    let raw = fs::read(&cmd.file).map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+
+    match read_version(&raw)? {
+        CURRENT_VERSION => {}
+        LEGACY_VERSION => {
+            return Err(format!(
+                "Bowl files from version {} have no checksums to verify",
+                LEGACY_VERSION
+            ))
+        }
+        v => return Err(format!("Unsupported bowl file version: {}", v)),
+    }
+
+    let verification = v0_1_0::verify(&raw)?;
+
+    let mut damaged = 0;
+    for (file_path, damage) in &verification.entries {
+        match damage {
+            Some(reason) => {
+                damaged += 1;
+                println!("DAMAGED {} ({})", file_path, reason);
+            }
+            None => println!("ok      {}", file_path),
+        }
+    }
+
+    println!(
+        "Archive digest: {} ({})",
+        to_hex(&verification.archive_digest),
+        if verification.archive_intact {
+            "ok"
+        } else {
+            "MISMATCH"
+        }
+    );
+
+    let mut bad_signature = false;
+    match &verification.signature {
+        Some(signature) => {
+            let valid = signature.verify(&verification.archive_digest).is_ok();
+            bad_signature = !valid;
+            println!(
+                "Signed by: {} ({})",
+                to_hex(&signature.public_key),
+                if valid { "ok" } else { "INVALID" }
+            );
+        }
+        None => println!("Signed by: nobody"),
+    }
+
+    if bad_signature {
+        return Err("Verification failed: invalid signature".into());
+    }
+    if damaged > 0 || !verification.archive_intact {
+        return Err(format!(
+            "Verification failed: {} of {} entries damaged",
+            damaged,
+            verification.entries.len()
+        ));
+    }
+
+    println!("Verification succeeded!");
+    Ok(())
+}
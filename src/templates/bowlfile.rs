@@ -1,281 +1,134 @@
 //! This file contains information about the encoding of Bowl files
 //!
-//! The encoding is as follows (capitalized means constant mapped to u8):
-//!
-//! BOWLFILE: BOWL + VERSION + F1 + F2 + F3 + ...
-//! FILE IN BOWLFILE: BOWL + FILE + <filename> + CONTENT + <escaped file contents>.
-//!
-//! *NOTE: This module will likely be broken into multiple later on*
-//! For example, I want to create separate files for versions of the BowlFile
+//! Every version of the encoding starts with BOWL + VERSION + <version>,
+//! which is used to pick the right decoder. The encodings themselves
+//! live in the `versions` module.
 
 use bowl_core::config::Config;
 use std::str::from_utf8;
 
-use super::escape::{escape_content, unescape_content};
+use super::compression::Compression;
 use super::files::FileContent;
-use super::symbols::{BOWL_CHAR, CONTENT_CHAR, CURRENT_VERSION, ESC_CHAR, FILE_CHAR, VERSION_CHAR};
+use super::stream::BowlWriter;
+use super::symbols::{CURRENT_VERSION, LEGACY_VERSION};
+use super::versions::{read_version, v0_0_1, v0_1_0};
 
 /// Represents the parsed version of a bowl template
 #[derive(Debug, Clone)]
 pub struct BowlFile {
     pub version: String,
+    pub compression: Compression,
     pub files: Vec<FileContent>,
 }
 
-#[derive(Clone)]
-enum DecodeState {
-    Reading,
-    ReadingVersionNum(i32),
-    ReadingFileName,
-    ReadingFileContent,
-}
-
 impl BowlFile {
     pub fn new(files: Vec<FileContent>) -> Self {
         Self {
             version: CURRENT_VERSION.to_owned(),
+            compression: Compression::None,
             files,
         }
     }
 
-    /// Parse a BowlFile
-    /// NOTE: This may be a good use case for Nom
-    pub fn decode(raw: Vec<u8>) -> Result<Self, &'static str> {
-        let mut version = String::new();
-        let mut files = Vec::new();
-        let mut bytes = raw.as_slice();
-        let mut reading_state = DecodeState::Reading;
-        let mut file_path = String::new();
-        let mut file_content = Vec::new();
-        loop {
-            match (bytes, reading_state.clone()) {
-                ([x, FILE_CHAR, rest @ ..], DecodeState::ReadingVersionNum(2))
-                    if *x != ESC_CHAR =>
-                {
-                    version.push(*x as char);
-                    reading_state = DecodeState::ReadingFileName;
-                    bytes = rest;
-                }
-                ([x @ 46, rest @ ..], DecodeState::ReadingVersionNum(y)) => {
-                    reading_state = DecodeState::ReadingVersionNum(y + 1);
-                    version.push(*x as char);
-                    bytes = rest;
-                }
-                ([x, rest @ ..], DecodeState::ReadingVersionNum(_)) => {
-                    version.push(*x as char);
-                    bytes = rest;
-                }
-                ([BOWL_CHAR, VERSION_CHAR, rest @ ..], DecodeState::Reading) => {
-                    reading_state = DecodeState::ReadingVersionNum(0);
-                    bytes = rest;
-                }
-                ([x, FILE_CHAR, rest @ ..], DecodeState::Reading) if *x != ESC_CHAR => {
-                    reading_state = DecodeState::ReadingFileName;
-                    bytes = rest;
-                }
-                ([x, CONTENT_CHAR, rest @ ..], DecodeState::ReadingFileName) if *x != ESC_CHAR => {
-                    file_path.push(*x as char);
-                    reading_state = DecodeState::ReadingFileContent;
-                    bytes = rest;
-                }
-                ([x, rest @ ..], DecodeState::ReadingFileName) => {
-                    file_path.push(*x as char);
-                    bytes = rest;
-                }
-                ([x, FILE_CHAR, rest @ ..], DecodeState::ReadingFileContent) if *x != ESC_CHAR => {
-                    file_content.push(*x);
-                    files.push(FileContent {
-                        file_path,
-                        content: unescape_content(file_content),
-                    });
-                    file_path = String::new();
-                    file_content = Vec::new();
-                    bytes = rest;
-                    reading_state = DecodeState::ReadingFileName;
-                }
-                ([x, rest @ ..], DecodeState::ReadingFileContent) => {
-                    file_content.push(*x);
-                    bytes = rest;
-                }
-                ([], DecodeState::ReadingFileContent) => {
-                    files.push(FileContent {
-                        file_path,
-                        content: unescape_content(file_content),
-                    });
-                    break;
-                }
-                _ => {
-                    return Err("Invalid bowl file provided");
-                }
-            }
-        }
+    /// Set the codec used for the payloads when encoding
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
 
-        // TODO: validate that the version is actually in form X.X.X
+    /// Parse a BowlFile of any supported version, decompressing
+    /// its contents if needed
+    pub fn decode(raw: Vec<u8>) -> Result<Self, String> {
+        let version = read_version(&raw)?.to_owned();
+        let (compression, files) = match version.as_str() {
+            LEGACY_VERSION => (Compression::None, v0_0_1::decode(&raw)?),
+            CURRENT_VERSION => v0_1_0::decode(&raw)?,
+            v => return Err(format!("Unsupported bowl file version: {}", v)),
+        };
 
-        Ok(Self { version, files })
+        Ok(Self {
+            version,
+            compression,
+            files,
+        })
     }
 
-    /// Encode the files provided in the bowl format.
-    pub fn encode(&self) -> Vec<u8> {
-        let mut result = Vec::new();
-
-        result.push(BOWL_CHAR);
-        result.push(VERSION_CHAR);
-        for b in CURRENT_VERSION.as_bytes() {
-            result.push(*b);
+    /// Encode the files provided in the current bowl format.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut writer = BowlWriter::new(Vec::new(), self.compression)?;
+        for f in &self.files {
+            writer.write_file(f)?;
         }
-        for f in self.files.clone() {
-            result.push(FILE_CHAR);
-            for c in f.file_path.as_bytes() {
-                result.push(*c);
-            }
-            result.push(CONTENT_CHAR);
-            result.append(&mut escape_content(f.content));
-        }
-
-        result
+        writer.finish()
     }
 
     /// Retrieve the bowl.toml config from BowlFile
     pub fn get_config(&self) -> Result<Config, String> {
         for file in &self.files {
             if file.file_path == "./bowl.toml" {
-                let content = from_utf8(&file.content)
-                    .map_err(|e| format!("Failed to decode config file: {}", e))?;
-                let config: Config = toml::from_str(content)
-                    .map_err(|e| format!("Error parsing bowl.toml: {}", e))?;
-                return Ok(config);
+                return parse_config(&file.content);
             }
         }
 
-        todo!()
+        Err("Bowlfile has no bowl.toml".into())
     }
 }
 
+/// Parse the raw content of a bowl.toml file
+pub fn parse_config(content: &[u8]) -> Result<Config, String> {
+    let content = from_utf8(content).map_err(|e| format!("Failed to decode config file: {}", e))?;
+    toml::from_str(content).map_err(|e| format!("Error parsing bowl.toml: {}", e))
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::templates::symbols::{BOWL_CHAR, CONTENT_CHAR, FILE_CHAR, VERSION_CHAR};
+
     #[test]
     fn test_encode_decode_content() {
         // ensures some level of bijectivity between these two functions
-        let bf = BowlFile {
-            version: CURRENT_VERSION.to_owned(),
-            files: vec![
-                FileContent {
-                    file_path: "README.md".into(),
-                    content: "# My epic readme\nGamer".into(),
-                },
-                FileContent {
-                    file_path: "src/main.rs".into(),
-                    content: "pub fn main() {println!(\"Hello, world\")}".into(),
-                },
-            ],
-        };
-
-        let expected = vec![
-            BOWL_CHAR,
-            VERSION_CHAR,
-            48,
-            46,
-            48,
-            46,
-            49,
-            FILE_CHAR,
-            82,
-            69,
-            65,
-            68,
-            77,
-            69,
-            46,
-            109,
-            100,
-            CONTENT_CHAR,
-            35,
-            32,
-            77,
-            121,
-            32,
-            101,
-            112,
-            105,
-            99,
-            32,
-            114,
-            101,
-            97,
-            100,
-            109,
-            101,
-            10,
-            71,
-            97,
-            109,
-            101,
-            114,
-            FILE_CHAR,
-            115,
-            114,
-            99,
-            47,
-            109,
-            97,
-            105,
-            110,
-            46,
-            114,
-            115,
-            CONTENT_CHAR,
-            112,
-            117,
-            98,
-            32,
-            102,
-            110,
-            32,
-            109,
-            97,
-            105,
-            110,
-            40,
-            41,
-            32,
-            123,
-            112,
-            114,
-            105,
-            110,
-            116,
-            108,
-            110,
-            33,
-            40,
-            34,
-            72,
-            101,
-            108,
-            108,
-            111,
-            44,
-            32,
-            119,
-            111,
-            114,
-            108,
-            100,
-            34,
-            41,
-            125,
-        ];
+        let bf = BowlFile::new(vec![
+            FileContent::new("README.md".into(), "# My epic readme\nGamer".into()),
+            FileContent::new(
+                "src/main.rs".into(),
+                "pub fn main() {println!(\"Hello, world\")}".into(),
+            ),
+        ]);
 
-        assert_eq!(bf.encode(), expected);
-        let nbf = BowlFile::decode(expected).unwrap();
+        let nbf = BowlFile::decode(bf.encode().unwrap()).unwrap();
 
+        assert_eq!(nbf.version, CURRENT_VERSION);
         assert_eq!(bf.files.len(), nbf.files.len());
         for i in 0..bf.files.len() {
             assert_eq!(bf.files[i].file_path, nbf.files[i].file_path);
             assert_eq!(bf.files[i].content, nbf.files[i].content);
         }
     }
+
+    #[test]
+    fn test_decode_dispatches_on_version() {
+        let mut legacy = vec![BOWL_CHAR, VERSION_CHAR];
+        legacy.extend_from_slice(LEGACY_VERSION.as_bytes());
+        legacy.push(FILE_CHAR);
+        legacy.extend_from_slice(b"a.txt");
+        legacy.push(CONTENT_CHAR);
+        legacy.extend_from_slice(b"hello");
+
+        let bf = BowlFile::decode(legacy).unwrap();
+        assert_eq!(bf.version, LEGACY_VERSION);
+        assert_eq!(bf.files[0].file_path, "a.txt");
+        assert_eq!(bf.files[0].content, b"hello");
+
+        let mut unknown = vec![BOWL_CHAR, VERSION_CHAR];
+        unknown.extend_from_slice(b"9.9.9");
+        assert!(BowlFile::decode(unknown).is_err());
+    }
+
+    #[test]
+    fn test_get_config_without_bowl_toml() {
+        let bf = BowlFile::new(vec![FileContent::new("README.md".into(), "# Hi".into())]);
+        assert_eq!(bf.get_config().unwrap_err(), "Bowlfile has no bowl.toml");
+    }
 }
@@ -0,0 +1,485 @@
+//! The `[command.<name>]` tables of bowl.toml, run with `bowl run`
+//!
+//! A command either matches on its first argument with `args`, or has
+//! `branches` of its own. Branches are tried in order and the first one
+//! whose requirements are met is run.
+//!
+//! A branch can run a script kept under the template's `bowl/`
+//! directory. `{{script}}` in its steps is replaced by the script's path
+//! and `{{...args}}` by the arguments left over after matching.
+
+use std::{
+    env::consts::EXE_EXTENSION,
+    path::{Component, Path, PathBuf},
+};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Deserializer};
+
+/// Directory of a template holding files that are only used by commands
+pub const SCRIPTS_DIR: &str = "bowl";
+
+/// A command declared by a template
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TemplateCommand {
+    /// Branches picked by the first argument given to the command
+    #[serde(default)]
+    pub args: Vec<ArgMatch>,
+    /// Branches used when no argument matched
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+}
+
+/// Branches used when the first argument is `value`
+#[derive(Deserialize, Debug, Clone)]
+pub struct ArgMatch {
+    pub value: String,
+    pub branches: Vec<Branch>,
+}
+
+/// One way of carrying out a command
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Branch {
+    /// Tools that have to be installed for the branch to run
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub has: Vec<String>,
+    /// When any of these are set, the branch only runs on those systems
+    #[serde(default)]
+    pub on_windows: bool,
+    #[serde(default)]
+    pub on_mac: bool,
+    #[serde(default)]
+    pub on_linux: bool,
+    #[serde(default)]
+    pub on_other: bool,
+    /// Shell commands run one after another
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub exec: Vec<Step>,
+    /// Script under `bowl/` that the branch runs
+    pub script: Option<String>,
+    /// Compiles the script in its own directory before the first run
+    pub build: Option<String>,
+}
+
+/// A shell command, written as `"cmd"` or `{ cmd = "cmd" }`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Step {
+    Cmd(String),
+    Table { cmd: String },
+}
+
+impl Step {
+    pub fn cmd(&self) -> &str {
+        match self {
+            Step::Cmd(cmd) | Step::Table { cmd } => cmd,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+/// Accept either a single value or a list of them
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Operating systems a branch can be limited to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Os {
+    Windows,
+    Mac,
+    Linux,
+    Other,
+}
+
+impl Os {
+    /// The system bowl is running on
+    pub fn current() -> Self {
+        if cfg!(target_os = "windows") {
+            Os::Windows
+        } else if cfg!(target_os = "macos") {
+            Os::Mac
+        } else if cfg!(target_os = "linux") {
+            Os::Linux
+        } else {
+            Os::Other
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Os::Windows => "windows",
+            Os::Mac => "mac",
+            Os::Linux => "linux",
+            Os::Other => "other systems",
+        }
+    }
+}
+
+impl Branch {
+    fn systems(&self) -> Vec<Os> {
+        [
+            (self.on_windows, Os::Windows),
+            (self.on_mac, Os::Mac),
+            (self.on_linux, Os::Linux),
+            (self.on_other, Os::Other),
+        ]
+        .into_iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, os)| os)
+        .collect()
+    }
+
+    /// Why the branch can't run here, if it can't
+    pub fn blocker(&self, os: Os, has: &impl Fn(&str) -> bool) -> Option<String> {
+        let systems = self.systems();
+        if !systems.is_empty() && !systems.contains(&os) {
+            let names = systems.iter().map(|os| os.name()).collect::<Vec<_>>();
+            return Some(format!("only runs on {}", names.join(", ")));
+        }
+        let missing = self
+            .has
+            .iter()
+            .filter(|tool| !has(tool))
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        if !missing.is_empty() {
+            return Some(format!("needs {}", missing.join(", ")));
+        }
+        None
+    }
+
+    /// Short description of what the branch needs
+    pub fn requirements(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.has.is_empty() {
+            parts.push(format!("has {}", self.has.join(", ")));
+        }
+        let systems = self.systems();
+        if !systems.is_empty() {
+            let names = systems.iter().map(|os| os.name()).collect::<Vec<_>>();
+            parts.push(format!("on {}", names.join(", ")));
+        }
+        match parts.is_empty() {
+            true => "no requirements".into(),
+            false => parts.join(", "),
+        }
+    }
+
+    /// Make sure the branch can do something
+    pub fn check(&self) -> Result<(), String> {
+        let Some(script) = &self.script else {
+            if self.exec.is_empty() {
+                return Err("has nothing to exec".into());
+            }
+            return Ok(());
+        };
+        let mut components = Path::new(script).components();
+        let inside = components.next() == Some(Component::Normal(SCRIPTS_DIR.as_ref()))
+            && components.all(|c| matches!(c, Component::Normal(_)));
+        if !inside {
+            return Err(format!(
+                "script \"{}\" has to be inside the {}/ directory",
+                script, SCRIPTS_DIR
+            ));
+        }
+        if self.exec.is_empty() && self.build.is_none() && self.has.is_empty() {
+            return Err(format!(
+                "needs exec or has to know how to run \"{}\"",
+                script
+            ));
+        }
+        Ok(())
+    }
+
+    /// Program the build step leaves next to `script`
+    pub fn program(script: &Path) -> PathBuf {
+        script.with_extension(EXE_EXTENSION)
+    }
+
+    /// The build step with `script` filled in, if there is one
+    pub fn build_command(&self, script: &Path) -> Option<String> {
+        let build = self.build.as_ref()?;
+        Some(interpolate(build, Some(script), &[]))
+    }
+
+    /// Shell commands to run, with `script` and `args` filled in. A
+    /// script without `exec` is run by its compiled program or by the
+    /// first tool it `has`.
+    pub fn commands(&self, script: Option<&Path>, args: &[String]) -> Vec<String> {
+        if !self.exec.is_empty() {
+            return self
+                .exec
+                .iter()
+                .map(|step| interpolate(step.cmd(), script, args))
+                .collect();
+        }
+        let Some(script) = script else {
+            return Vec::new();
+        };
+        let run = match (&self.build, self.has.first().map(String::as_str)) {
+            (Some(_), _) => quote(&Self::program(script).to_string_lossy()),
+            (None, Some("go")) => "go run {{script}}".into(),
+            (None, Some("deno")) => "deno run -A {{script}}".into(),
+            (None, Some(tool)) => format!("{} {{{{script}}}}", tool),
+            (None, None) => "{{script}}".into(),
+        };
+        vec![interpolate(
+            &format!("{} {{{{...args}}}}", run),
+            Some(script),
+            args,
+        )]
+    }
+}
+
+/// Quote an argument for the system shell when it needs it
+fn quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_owned()
+    } else if cfg!(windows) {
+        format!("\"{}\"", arg.replace('"', "\"\""))
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Fill `{{script}}` and `{{...args}}` into a shell command
+pub fn interpolate(cmd: &str, script: Option<&Path>, args: &[String]) -> String {
+    // both tags are filled in one pass so tags inside arguments stay as typed
+    let tag = Regex::new(r"\{\{\s*(script|\.\.\.args)\s*\}\}").unwrap();
+    let args = args.iter().map(|a| quote(a)).collect::<Vec<String>>();
+    let args = args.join(" ");
+    let cmd = tag.replace_all(cmd, |caps: &Captures| match (&caps[1], script) {
+        ("script", Some(script)) => quote(&script.to_string_lossy()),
+        ("script", None) => caps[0].to_owned(),
+        _ => args.clone(),
+    });
+    cmd.trim_end().to_owned()
+}
+
+/// The branch picked to run, along with why earlier ones were skipped
+pub struct Choice<'a> {
+    /// Position of the branch, counting from 1
+    pub number: usize,
+    pub branch: &'a Branch,
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Pick the first branch that can run here. When none can, every
+/// branch is returned with the reason it was skipped.
+pub fn choose<'a>(
+    branches: &'a [Branch],
+    os: Os,
+    has: impl Fn(&str) -> bool,
+) -> Result<Choice<'a>, Vec<(usize, String)>> {
+    let mut skipped = Vec::new();
+    for (i, branch) in branches.iter().enumerate() {
+        match branch.blocker(os, &has) {
+            Some(reason) => skipped.push((i + 1, reason)),
+            None => {
+                return Ok(Choice {
+                    number: i + 1,
+                    branch,
+                    skipped,
+                })
+            }
+        }
+    }
+    Err(skipped)
+}
+
+impl TemplateCommand {
+    /// Branches to try for the given arguments, along with the arguments
+    /// that are left once one has been matched
+    pub fn branches_for<'a, 'b>(
+        &'a self,
+        args: &'b [String],
+    ) -> Result<(&'a [Branch], &'b [String]), String> {
+        if let Some((first, rest)) = args.split_first() {
+            if let Some(matched) = self.args.iter().find(|a| a.value == *first) {
+                return Ok((&matched.branches, rest));
+            }
+        }
+        if !self.branches.is_empty() {
+            return Ok((&self.branches, args));
+        }
+        let values = self
+            .args
+            .iter()
+            .map(|a| a.value.as_str())
+            .collect::<Vec<&str>>();
+        Err(match args.first() {
+            Some(first) => format!("\"{}\" isn't one of {}", first, values.join(", ")),
+            None => format!("Expected one of {}", values.join(", ")),
+        })
+    }
+
+    /// Make sure the command is usable
+    pub fn check(&self) -> Result<(), String> {
+        if self.args.is_empty() && self.branches.is_empty() {
+            return Err("has no args or branches".into());
+        }
+        for (i, branch) in self.branches.iter().enumerate() {
+            branch
+                .check()
+                .map_err(|e| format!("branch {} {}", i + 1, e))?;
+        }
+        for arg in &self.args {
+            if arg.branches.is_empty() {
+                return Err(format!("\"{}\" has no branches", arg.value));
+            }
+            for (i, branch) in arg.branches.iter().enumerate() {
+                branch
+                    .check()
+                    .map_err(|e| format!("\"{}\" branch {} {}", arg.value, i + 1, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn commands() -> BTreeMap<String, TemplateCommand> {
+        #[derive(Deserialize)]
+        struct Doc {
+            command: BTreeMap<String, TemplateCommand>,
+        }
+        let doc: Doc = toml::from_str(
+            r#"
+            [command.add]
+            args = [
+                { value = "tailwind", branches = [
+                    { has = ["npm"], exec = [{ cmd = "npx tailwindcss init" }] },
+                ] },
+                { value = "postgres", branches = [
+                    { on_windows = true, exec = "choco install postgresql" },
+                    { has = "go", exec = [
+                        { cmd = "go get github.com/jmoiron/sqlx" },
+                        "goose create add_schema sql",
+                    ] },
+                ] },
+            ]
+
+            [command.fmt]
+            branches = [{ exec = "gofmt -w ." }]
+            "#,
+        )
+        .unwrap();
+        doc.command
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let commands = commands();
+        assert!(commands.values().all(|c| c.check().is_ok()));
+
+        let postgres = &commands["add"].args[1].branches;
+        assert!(postgres[0].on_windows);
+        assert_eq!(postgres[1].has, ["go"]);
+        assert_eq!(postgres[1].exec[1].cmd(), "goose create add_schema sql");
+        assert_eq!(commands["fmt"].branches[0].exec[0].cmd(), "gofmt -w .");
+    }
+
+    #[test]
+    fn test_match_args() {
+        let commands = commands();
+        let args = ["postgres".to_owned(), "extra".to_owned()];
+        let (branches, rest) = commands["add"].branches_for(&args).unwrap();
+        assert_eq!(branches.len(), 2);
+        assert_eq!(rest, ["extra"]);
+
+        assert!(commands["add"].branches_for(&["mysql".into()]).is_err());
+        assert!(commands["add"].branches_for(&[]).is_err());
+        let args = ["x".to_owned()];
+        let (_, rest) = commands["fmt"].branches_for(&args).unwrap();
+        assert_eq!(rest, ["x"]);
+    }
+
+    #[test]
+    fn test_choose_branch() {
+        let commands = commands();
+        let postgres = &commands["add"].args[1].branches;
+
+        let choice = choose(postgres, Os::Linux, |tool| tool == "go")
+            .ok()
+            .unwrap();
+        assert_eq!(choice.number, 2);
+        assert_eq!(choice.skipped, [(1, "only runs on windows".to_owned())]);
+
+        let choice = choose(postgres, Os::Windows, |_| false).ok().unwrap();
+        assert_eq!(choice.number, 1);
+
+        let skipped = choose(postgres, Os::Mac, |_| false).err().unwrap();
+        assert_eq!(skipped[1], (2, "needs go".to_owned()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_script_commands() {
+        let doc: BTreeMap<String, BTreeMap<String, TemplateCommand>> = toml::from_str(
+            r#"
+            [command.add]
+            branches = [
+                { has = "go", script = "bowl/scripts/add.go", exec = "go run {{script}} {{...args}}" },
+                { has = "rustc", script = "bowl/scripts/add.rs", build = "rustc -C opt-level=3 {{ script }}" },
+                { has = "python3", script = "bowl/scripts/add.py" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let branches = &doc["command"]["add"].branches;
+        assert!(branches.iter().all(|b| b.check().is_ok()));
+
+        let script = Path::new("/cache/bowl/scripts/add.go");
+        let args = ["tailwind".to_owned(), "it's".to_owned()];
+        assert_eq!(
+            branches[0].commands(Some(script), &args),
+            [r#"go run /cache/bowl/scripts/add.go tailwind 'it'\''s'"#]
+        );
+
+        let script = Path::new("/cache/bowl/scripts/add.rs");
+        assert_eq!(
+            branches[1].build_command(script).unwrap(),
+            "rustc -C opt-level=3 /cache/bowl/scripts/add.rs"
+        );
+        assert_eq!(
+            branches[1].commands(Some(script), &[]),
+            ["/cache/bowl/scripts/add"]
+        );
+
+        let script = Path::new("/cache/bowl/scripts/add.py");
+        assert_eq!(
+            branches[2].commands(Some(script), &["$HOME".into()]),
+            ["python3 /cache/bowl/scripts/add.py '$HOME'"]
+        );
+        // tags typed as arguments are passed along untouched
+        assert_eq!(
+            branches[2].commands(Some(script), &["{{script}}".into()]),
+            ["python3 /cache/bowl/scripts/add.py '{{script}}'"]
+        );
+
+        let outside = Branch {
+            script: Some("scripts/add.py".into()),
+            ..branches[2].clone()
+        };
+        assert!(outside.check().is_err());
+    }
+}
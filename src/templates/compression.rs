@@ -0,0 +1,92 @@
+use std::io::{self, Read, Write};
+
+use clap::ValueEnum;
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+/// Codec applied to every payload in a bowlfile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// Store payloads as-is
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Value stored in the bowlfile header
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Gzip => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, String> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Gzip),
+            2 => Ok(Compression::Zstd),
+            b => Err(format!("Unknown compression codec in bowl file: {}", b)),
+        }
+    }
+
+    /// Compress everything read from `input` into `output`
+    pub fn compress_to<R: Read, W: Write>(self, mut input: R, mut output: W) -> Result<(), String> {
+        match self {
+            Compression::None => io::copy(&mut input, &mut output).map(|_| ()),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(output, flate2::Compression::default());
+                io::copy(&mut input, &mut encoder).and_then(|_| encoder.finish().map(|_| ()))
+            }
+            Compression::Zstd => {
+                zstd::stream::copy_encode(input, output, zstd::DEFAULT_COMPRESSION_LEVEL)
+            }
+        }
+        .map_err(|e| format!("Failed to compress file: {}", e))
+    }
+
+    /// Wrap `input` so that reading from it yields the decompressed bytes
+    pub fn decoder<'a, R: Read + 'a>(self, input: R) -> Result<Box<dyn Read + 'a>, String> {
+        Ok(match self {
+            Compression::None => Box::new(input),
+            Compression::Gzip => Box::new(GzDecoder::new(input)),
+            Compression::Zstd => Box::new(
+                zstd::Decoder::new(input)
+                    .map_err(|e| format!("Failed to decompress file: {}", e))?,
+            ),
+        })
+    }
+
+    pub fn compress(self, content: &[u8]) -> Result<Vec<u8>, String> {
+        let mut result = Vec::new();
+        self.compress_to(content, &mut result)?;
+        Ok(result)
+    }
+
+    pub fn decompress(self, content: &[u8]) -> Result<Vec<u8>, String> {
+        let mut result = Vec::new();
+        self.decoder(content)?
+            .read_to_end(&mut result)
+            .map_err(|e| format!("Failed to decompress file: {}", e))?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_decompress() {
+        let content = "lockfile entry\n".repeat(500).into_bytes();
+        for codec in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let compressed = codec.compress(&content).unwrap();
+            if codec != Compression::None {
+                assert!(compressed.len() < content.len());
+            }
+            assert_eq!(codec.decompress(&compressed).unwrap(), content);
+            assert_eq!(Compression::from_byte(codec.to_byte()).unwrap(), codec);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Template settings in bowl.toml that `bowl_core::config::Config`
+//! doesn't cover. Both are read from the same file.
+
+use std::{collections::BTreeMap, str::from_utf8};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+
+use super::command::TemplateCommand;
+use super::prompt::{Variable, Variables};
+use super::render::parse_expr;
+use crate::store::check_name;
+
+/// Extra sections of bowl.toml used when a template is used
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TemplateConfig {
+    #[serde(default)]
+    pub template: About,
+    /// Asked for in order before any file is written
+    #[serde(default)]
+    pub variables: Vec<Variable>,
+    #[serde(default)]
+    pub render: Render,
+    #[serde(default)]
+    pub files: Files,
+    /// Commands run with `bowl run`, by name
+    #[serde(default)]
+    pub command: BTreeMap<String, TemplateCommand>,
+}
+
+/// What the `[template]` section of bowl.toml says about a template
+/// besides its name, version and source
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct About {
+    /// One line shown in search results
+    #[serde(default)]
+    pub description: String,
+    /// Scope the template is published under, so `bowl publish` picks
+    /// the registry serving it, as `bowl use @scope/name` does
+    pub scope: Option<String>,
+    /// Registry `bowl publish` sends the template to, by name or URL
+    pub registry: Option<String>,
+}
+
+/// The `[files]` section of bowl.toml
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Files {
+    #[serde(default)]
+    pub conditional: Vec<Conditional>,
+    /// Leave out what the project's `.gitignore` ignores when packing
+    #[serde(default)]
+    pub gitignore: bool,
+    /// Replaces the patterns left out of every template when set, see
+    /// `patterns::DEFAULT_EXCLUDES`
+    pub default_excludes: Option<Vec<String>>,
+}
+
+/// Files that are only generated when `when` holds, declared with
+/// `[[files.conditional]]`
+#[derive(Deserialize, Debug, Clone)]
+pub struct Conditional {
+    /// Globs of the files, relative to the template root. Everything
+    /// inside a matching directory is included along with it.
+    pub paths: Vec<String>,
+    /// Expression over the template variables, like `"docker" in features`
+    pub when: String,
+}
+
+/// Conditional files worked out from the answers to a template
+pub struct Conditions {
+    excluded: Vec<GlobSet>,
+}
+
+impl Conditions {
+    /// Whether the entry at `file_path` should be generated
+    pub fn includes(&self, file_path: &str) -> bool {
+        let path = relative_path(file_path);
+        // a directory that's left out takes everything in it along
+        let mut ancestors = path
+            .match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .chain([path]);
+        !ancestors.any(|p| self.excluded.iter().any(|globs| globs.is_match(p)))
+    }
+}
+
+/// The `[render]` section of bowl.toml
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Render {
+    /// Globs of the files the answers are rendered into. Every other
+    /// file is written as it is.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Globs of files written as they are even though `include` matches
+    /// them, for files that use `{{ }}` for something else like CI workflows
+    #[serde(default)]
+    pub skip: Vec<String>,
+}
+
+/// Build a matcher for globs written relative to the template root
+pub fn glob_set(globs: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|e| format!("Invalid glob \"{}\": {}", glob, e))?);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Invalid globs in bowl.toml: {}", e))
+}
+
+/// Path of an entry relative to the template root, as globs see it
+pub fn relative_path(file_path: &str) -> &str {
+    file_path.strip_prefix("./").unwrap_or(file_path)
+}
+
+impl TemplateConfig {
+    /// Make sure every section is usable, so mistakes are caught when
+    /// publishing rather than when someone uses the template
+    pub fn check(&self) -> Result<(), String> {
+        for (i, variable) in self.variables.iter().enumerate() {
+            variable.check()?;
+            if self.variables[..i].iter().any(|v| v.name == variable.name) {
+                return Err(format!("Variable \"{}\" is declared twice", variable.name));
+            }
+        }
+        if let Some(scope) = &self.template.scope {
+            check_name(scope).map_err(|_| format!("Invalid scope \"{}\"", scope))?;
+        }
+        glob_set(&self.render.include)?;
+        glob_set(&self.render.skip)?;
+        for conditional in &self.files.conditional {
+            glob_set(&conditional.paths)?;
+            let expr = parse_expr(&conditional.when)
+                .map_err(|e| format!("Invalid condition \"{}\": {}", conditional.when, e))?;
+            for name in expr.variables() {
+                if name != "BOWL_NAME" && !self.variables.iter().any(|v| v.name == name) {
+                    return Err(format!(
+                        "Condition \"{}\" uses unknown variable \"{}\"",
+                        conditional.when, name
+                    ));
+                }
+            }
+        }
+        for (name, command) in &self.command {
+            command
+                .check()
+                .map_err(|e| format!("Command \"{}\" {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Scripts used by the commands of the template
+    pub fn scripts(&self) -> Vec<&str> {
+        self.command
+            .values()
+            .flat_map(|command| {
+                command
+                    .branches
+                    .iter()
+                    .chain(command.args.iter().flat_map(|arg| &arg.branches))
+            })
+            .filter_map(|branch| branch.script.as_deref())
+            .collect()
+    }
+
+    /// Evaluate every `[[files.conditional]]` against the answers
+    pub fn conditions(&self, variables: &Variables) -> Result<Conditions, String> {
+        let mut excluded = Vec::new();
+        for conditional in &self.files.conditional {
+            let holds = parse_expr(&conditional.when)
+                .and_then(|expr| expr.eval_bool(variables))
+                .map_err(|e| format!("Invalid condition \"{}\": {}", conditional.when, e))?;
+            if !holds {
+                excluded.push(glob_set(&conditional.paths)?);
+            }
+        }
+        Ok(Conditions { excluded })
+    }
+}
+
+/// Parse the template settings out of the raw content of a bowl.toml file
+pub fn parse_template_config(content: &[u8]) -> Result<TemplateConfig, String> {
+    let content = from_utf8(content).map_err(|e| format!("Failed to decode config file: {}", e))?;
+    toml::from_str(content).map_err(|e| format!("Error parsing bowl.toml: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::prompt::Value;
+
+    fn config() -> TemplateConfig {
+        parse_template_config(
+            br#"
+            [[variables]]
+            name = "features"
+            type = "multi_select"
+            options = ["docker", "ci"]
+
+            [[variables]]
+            name = "database"
+            type = "select"
+            options = ["postgres", "sqlite"]
+
+            [[files.conditional]]
+            paths = ["Dockerfile", ".dockerignore"]
+            when = '"docker" in features'
+
+            [[files.conditional]]
+            paths = ["db/postgres"]
+            when = 'database == "postgres"'
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_conditional_files() {
+        let config = config();
+        config.check().unwrap();
+        let variables = Variables::from([
+            ("features".into(), Value::List(vec!["docker".into()])),
+            ("database".into(), Value::Text("sqlite".into())),
+        ]);
+        let conditions = config.conditions(&variables).unwrap();
+
+        assert!(conditions.includes("./Dockerfile"));
+        assert!(conditions.includes("./main.go"));
+        assert!(conditions.includes("./db/sqlite/schema.sql"));
+        assert!(!conditions.includes("./db/postgres"));
+        assert!(!conditions.includes("./db/postgres/migrations/001.sql"));
+    }
+
+    #[test]
+    fn test_check_conditions() {
+        let mut config = config();
+        config.files.conditional[0].when = "docker and".into();
+        assert!(config.check().is_err());
+        config.files.conditional[0].when = "use_docker".into();
+        assert!(config.check().unwrap_err().contains("use_docker"));
+    }
+}
@@ -1,17 +1,131 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::symbols::DEFAULT_MODE;
+use crate::files::file_mode;
+
+/// What kind of filesystem entry a `FileContent` represents
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink { target: String },
+}
+
+impl EntryKind {
+    /// Value stored in the bowlfile for this kind
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            EntryKind::File => 0,
+            EntryKind::Directory => 1,
+            EntryKind::Symlink { .. } => 2,
+        }
+    }
+
+    /// Rebuild a kind from its stored value and link target
+    pub fn from_byte(byte: u8, target: String) -> Result<Self, String> {
+        match byte {
+            0 => Ok(EntryKind::File),
+            1 => Ok(EntryKind::Directory),
+            2 => Ok(EntryKind::Symlink { target }),
+            b => Err(format!("Unknown entry kind in bowl file: {}", b)),
+        }
+    }
+
+    /// Link target of a symlink, empty for anything else
+    pub fn target(&self) -> &str {
+        match self {
+            EntryKind::Symlink { target } => target,
+            _ => "",
+        }
+    }
+}
+
+/// Why `path` can't be used inside a project, if it can't. Absolute
+/// paths and NUL bytes are always refused, `..` unless `allow_parent`.
+fn invalid_path(path: &str, allow_parent: bool) -> Option<&'static str> {
+    let bytes = path.as_bytes();
+    if path.contains('\0') {
+        Some("it contains a NUL byte")
+    } else if path.starts_with(['/', '\\'])
+        || Path::new(path).has_root()
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+    {
+        Some("it is absolute")
+    } else if !allow_parent && path.split(['/', '\\']).any(|part| part == "..") {
+        Some("it points outside of the project")
+    } else {
+        None
+    }
+}
+
+/// Make sure an entry read from a bowlfile can only be extracted inside
+/// the project. Link targets may use `..` since they're relative to the
+/// link, whether they stay inside is checked when extracting.
+pub fn check_entry(file_path: &str, kind: &EntryKind) -> Result<(), String> {
+    if let Some(why) = invalid_path(file_path, false) {
+        return Err(format!(
+            "Invalid file name \"{}\" in bowl file: {}",
+            file_path.escape_debug(),
+            why
+        ));
+    }
+    let target = kind.target();
+    match invalid_path(target, true) {
+        Some(why) => Err(format!(
+            "Invalid link target \"{}\" for \"{}\" in bowl file: {}",
+            target.escape_debug(),
+            file_path,
+            why
+        )),
+        None => Ok(()),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct FileContent {
     /// File path relative to the caller
     pub file_path: String,
-    /// The inescaped content of the file
+    /// The inescaped content of the file. Always empty for
+    /// directories and symlinks
     pub content: Vec<u8>,
+    /// Unix permission bits
+    pub mode: u32,
+    pub kind: EntryKind,
 }
 
 impl FileContent {
+    /// A regular file with default permissions
+    pub fn new(file_path: String, content: Vec<u8>) -> Self {
+        Self {
+            file_path,
+            content,
+            mode: DEFAULT_MODE,
+            kind: EntryKind::File,
+        }
+    }
+
+    /// Read an entry from disk without following symlinks
     pub fn from_path(path: PathBuf) -> Result<Self, String> {
         let file_path = path.clone().to_str().unwrap().to_owned();
-        let content = fs::read(path).map_err(|e| e.to_string())?;
-        Ok(Self { file_path, content })
+        let metadata = fs::symlink_metadata(&path).map_err(|e| e.to_string())?;
+        let mode = file_mode(&metadata);
+        let (kind, content) = if metadata.is_symlink() {
+            let target = fs::read_link(&path).map_err(|e| e.to_string())?;
+            let target = target.to_str().unwrap().to_owned();
+            (EntryKind::Symlink { target }, Vec::new())
+        } else if metadata.is_dir() {
+            (EntryKind::Directory, Vec::new())
+        } else {
+            (EntryKind::File, fs::read(path).map_err(|e| e.to_string())?)
+        };
+        Ok(Self {
+            file_path,
+            content,
+            mode,
+            kind,
+        })
     }
 }
@@ -0,0 +1,150 @@
+//! Two way merge of a file that already exists with the one a template
+//! would write over it
+//!
+//! Lines only one side has are kept, so additions from either side
+//! survive. Where both sides changed the same lines, both versions are
+//! kept between git style conflict markers.
+
+/// Above this many line pairs the files are treated as one big conflict
+/// rather than diffed
+const MAX_DIFF: usize = 4_000_000;
+
+/// Result of merging two files
+pub struct Merged {
+    pub content: String,
+    /// Number of sections left between conflict markers
+    pub conflicts: usize,
+}
+
+/// Lines of `text`, each with its line ending
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// For every position in `a` and `b`, whether the line is part of their
+/// longest common subsequence
+fn common_lines(a: &[&str], b: &[&str]) -> (Vec<bool>, Vec<bool>) {
+    let (n, m) = (a.len(), b.len());
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[at(i, j)] = if a[i] == b[j] {
+                table[at(i + 1, j + 1)] + 1
+            } else {
+                table[at(i + 1, j)].max(table[at(i, j + 1)])
+            };
+        }
+    }
+
+    let (mut in_a, mut in_b) = (vec![false; n], vec![false; m]);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            in_a[i] = true;
+            in_b[j] = true;
+            i += 1;
+            j += 1;
+        } else if table[at(i + 1, j)] >= table[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (in_a, in_b)
+}
+
+fn push_conflict(out: &mut String, ours: &[&str], theirs: &[&str]) {
+    let section = |out: &mut String, lines: &[&str]| {
+        for line in lines {
+            out.push_str(line);
+        }
+        if lines.last().is_some_and(|l| !l.ends_with('\n')) {
+            out.push('\n');
+        }
+    };
+    out.push_str("<<<<<<< existing\n");
+    section(out, ours);
+    out.push_str("=======\n");
+    section(out, theirs);
+    out.push_str(">>>>>>> template\n");
+}
+
+/// Merge `incoming` from the template into the `existing` file
+pub fn merge(existing: &str, incoming: &str) -> Merged {
+    let (a, b) = (lines(existing), lines(incoming));
+    // lines both files start and end with don't need diffing
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut out = a[..prefix].concat();
+    let mut conflicts = 0;
+    if mid_a.len().saturating_mul(mid_b.len()) > MAX_DIFF {
+        push_conflict(&mut out, mid_a, mid_b);
+        conflicts += 1;
+    } else {
+        let (in_a, in_b) = common_lines(mid_a, mid_b);
+        let (mut i, mut j) = (0, 0);
+        while i < mid_a.len() || j < mid_b.len() {
+            if i < mid_a.len() && j < mid_b.len() && in_a[i] && in_b[j] {
+                out.push_str(mid_a[i]);
+                i += 1;
+                j += 1;
+                continue;
+            }
+            // gather what each side has before the next common line
+            let start = (i, j);
+            while i < mid_a.len() && !in_a[i] {
+                i += 1;
+            }
+            while j < mid_b.len() && !in_b[j] {
+                j += 1;
+            }
+            let (ours, theirs) = (&mid_a[start.0..i], &mid_b[start.1..j]);
+            if ours.is_empty() || theirs.is_empty() {
+                out.push_str(&ours.concat());
+                out.push_str(&theirs.concat());
+            } else {
+                push_conflict(&mut out, ours, theirs);
+                conflicts += 1;
+            }
+        }
+    }
+    out.push_str(&a[a.len() - suffix..].concat());
+
+    Merged {
+        content: out,
+        conflicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_additions() {
+        let merged = merge("target\n.env\n", "target\nnode_modules\n");
+        assert_eq!(merged.conflicts, 1);
+
+        let merged = merge("a\nmine\nb\n", "a\nb\ntheirs\n");
+        assert_eq!(merged.content, "a\nmine\nb\ntheirs\n");
+        assert_eq!(merged.conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let merged = merge("a\nold\nb", "a\nnew\nb");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.content,
+            "a\n<<<<<<< existing\nold\n=======\nnew\n>>>>>>> template\nb"
+        );
+    }
+}
@@ -1,5 +1,14 @@
 pub mod bowlfile;
+pub mod command;
+pub mod compression;
+pub mod config;
+pub mod digest;
 pub mod escape;
 pub mod files;
+pub mod merge;
 pub mod prompt;
+pub mod render;
+pub mod signature;
+pub mod stream;
 pub mod symbols;
+pub mod versions;
@@ -1,8 +1,352 @@
-use serde::{Serialize, Deserialize};
+use std::{collections::BTreeMap, fmt};
+
+use inquire::{validator::Validation, Confirm, MultiSelect, Select, Text};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 /// Deserializable wrapper for Inquire prompting tools
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Prompt {
-    Text {},
-    Select {},
+    Text {
+        default: Option<String>,
+        /// Regex the answer has to match
+        validate: Option<String>,
+    },
+    Select {
+        options: Vec<String>,
+        default: Option<String>,
+    },
+    MultiSelect {
+        options: Vec<String>,
+        #[serde(default)]
+        default: Vec<String>,
+    },
+    Confirm {
+        #[serde(default)]
+        default: bool,
+    },
+}
+
+/// A variable declared with `[[variables]]` in bowl.toml
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    /// Question asked for the variable, defaults to its name
+    pub prompt: Option<String>,
+    pub help: Option<String>,
+    #[serde(flatten)]
+    pub kind: Prompt,
+}
+
+/// An answer given for a variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    List(Vec<String>),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Text(text) => write!(f, "{}", text),
+            Value::List(items) => write!(f, "{}", items.join(", ")),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// Answers for every variable of a template, by name
+pub type Variables = BTreeMap<String, Value>;
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    // anchored so the whole answer has to match
+    Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|e| format!("Invalid validate regex \"{}\": {}", pattern, e))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Variable {
+    fn message(&self) -> &str {
+        self.prompt.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Make sure the declaration makes sense before anyone is asked
+    pub fn check(&self) -> Result<(), String> {
+        if !is_identifier(&self.name) {
+            return Err(format!(
+                "Invalid variable name \"{}\", use letters, digits and underscores",
+                self.name
+            ));
+        }
+        let in_options = |options: &[String], value: &String| {
+            if options.contains(value) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "Default \"{}\" of variable \"{}\" is not one of its options",
+                    value, self.name
+                ))
+            }
+        };
+        match &self.kind {
+            Prompt::Text { default, validate } => {
+                if let Some(pattern) = validate {
+                    let re = compile(pattern)?;
+                    if let Some(default) = default.as_ref().filter(|d| !re.is_match(d)) {
+                        return Err(format!(
+                            "Default \"{}\" of variable \"{}\" doesn't match \"{}\"",
+                            default, self.name, pattern
+                        ));
+                    }
+                }
+            }
+            Prompt::Select { options, default } => {
+                if options.is_empty() {
+                    return Err(format!("Variable \"{}\" has no options", self.name));
+                }
+                if let Some(default) = default {
+                    in_options(options, default)?;
+                }
+            }
+            Prompt::MultiSelect { options, default } => {
+                if options.is_empty() {
+                    return Err(format!("Variable \"{}\" has no options", self.name));
+                }
+                for value in default {
+                    in_options(options, value)?;
+                }
+            }
+            Prompt::Confirm { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Turn an answer given on the command line into a value.
+    /// Multi-select answers are comma separated.
+    pub fn parse(&self, raw: &str) -> Result<Value, String> {
+        let not_an_option = |value: &str| {
+            format!(
+                "\"{}\" is not an option for \"{}\"",
+                value.trim(),
+                self.name
+            )
+        };
+        match &self.kind {
+            Prompt::Text { validate, .. } => {
+                if let Some(pattern) = validate {
+                    if !compile(pattern)?.is_match(raw) {
+                        return Err(format!(
+                            "\"{}\" doesn't match \"{}\" for \"{}\"",
+                            raw, pattern, self.name
+                        ));
+                    }
+                }
+                Ok(Value::Text(raw.to_owned()))
+            }
+            Prompt::Select { options, .. } => match options.iter().find(|o| *o == raw) {
+                Some(option) => Ok(Value::Text(option.clone())),
+                None => Err(not_an_option(raw)),
+            },
+            Prompt::MultiSelect { options, .. } => raw
+                .split(',')
+                .filter(|value| !value.trim().is_empty())
+                .map(|value| match options.iter().find(|o| *o == value.trim()) {
+                    Some(option) => Ok(option.clone()),
+                    None => Err(not_an_option(value)),
+                })
+                .collect::<Result<Vec<String>, String>>()
+                .map(Value::List),
+            Prompt::Confirm { .. } => match raw {
+                "true" | "yes" | "y" => Ok(Value::Bool(true)),
+                "false" | "no" | "n" => Ok(Value::Bool(false)),
+                _ => Err(format!("\"{}\" expects true or false", self.name)),
+            },
+        }
+    }
+
+    /// Ask the user for a value
+    pub fn ask(&self) -> Result<Value, String> {
+        let help = self.help.as_deref();
+        let failed = |e: inquire::InquireError| format!("Failed to read \"{}\": {}", self.name, e);
+        match &self.kind {
+            Prompt::Text { default, validate } => {
+                let mut prompt = Text::new(self.message());
+                if let Some(default) = default {
+                    prompt = prompt.with_default(default);
+                }
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
+                }
+                if let Some(pattern) = validate {
+                    let re = compile(pattern)?;
+                    let pattern = pattern.clone();
+                    prompt = prompt.with_validator(move |input: &str| {
+                        Ok(if re.is_match(input) {
+                            Validation::Valid
+                        } else {
+                            Validation::Invalid(format!("Must match {}", pattern).into())
+                        })
+                    });
+                }
+                prompt.prompt().map(Value::Text).map_err(failed)
+            }
+            Prompt::Select { options, default } => {
+                let mut prompt = Select::new(self.message(), options.clone());
+                if let Some(i) = options.iter().position(|o| Some(o) == default.as_ref()) {
+                    prompt = prompt.with_starting_cursor(i);
+                }
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
+                }
+                prompt.prompt().map(Value::Text).map_err(failed)
+            }
+            Prompt::MultiSelect { options, default } => {
+                let selected = options
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, o)| default.contains(o))
+                    .map(|(i, _)| i)
+                    .collect::<Vec<usize>>();
+                let mut prompt =
+                    MultiSelect::new(self.message(), options.clone()).with_default(&selected);
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
+                }
+                prompt.prompt().map(Value::List).map_err(failed)
+            }
+            Prompt::Confirm { default } => {
+                let mut prompt = Confirm::new(self.message()).with_default(*default);
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
+                }
+                prompt.prompt().map(Value::Bool).map_err(failed)
+            }
+        }
+    }
+}
+
+/// Get a value for every variable, asking the user for any that weren't
+/// given up front as `name=value`
+pub fn collect(variables: &[Variable], given: &[String]) -> Result<Variables, String> {
+    let mut given = given
+        .iter()
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => Ok((name.trim(), value)),
+            None => Err(format!("Expected name=value, got \"{}\"", pair)),
+        })
+        .collect::<Result<BTreeMap<&str, &str>, String>>()?;
+
+    let mut values = Variables::new();
+    for variable in variables {
+        let value = match given.remove(variable.name.as_str()) {
+            Some(raw) => variable.parse(raw)?,
+            None => variable.ask()?,
+        };
+        values.insert(variable.name.clone(), value);
+    }
+
+    if let Some(name) = given.keys().next() {
+        return Err(format!("Template has no variable named \"{}\"", name));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Vec<Variable> {
+        #[derive(Deserialize)]
+        struct Doc {
+            variables: Vec<Variable>,
+        }
+        let doc: Doc = toml::from_str(
+            r#"
+            [[variables]]
+            name = "project_name"
+            prompt = "Name of your project?"
+            type = "text"
+            default = "my_app"
+            validate = "[a-z_]+"
+
+            [[variables]]
+            name = "license"
+            type = "select"
+            options = ["MIT", "Apache-2.0"]
+            default = "MIT"
+
+            [[variables]]
+            name = "features"
+            type = "multi_select"
+            options = ["docker", "ci"]
+
+            [[variables]]
+            name = "git"
+            type = "confirm"
+            help = "Initialize a git repository"
+            "#,
+        )
+        .unwrap();
+        doc.variables
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let variables = variables();
+        assert!(variables.iter().all(|v| v.check().is_ok()));
+        assert_eq!(
+            variables[1].kind,
+            Prompt::Select {
+                options: vec!["MIT".into(), "Apache-2.0".into()],
+                default: Some("MIT".into()),
+            }
+        );
+        assert_eq!(variables[3].kind, Prompt::Confirm { default: false });
+    }
+
+    #[test]
+    fn test_collect_given_values() {
+        let given = [
+            "project_name=blog".to_owned(),
+            "license=Apache-2.0".to_owned(),
+            "features=docker, ci".to_owned(),
+            "git=yes".to_owned(),
+        ];
+        let values = collect(&variables(), &given).unwrap();
+
+        assert_eq!(values["project_name"], Value::Text("blog".into()));
+        assert_eq!(values["license"], Value::Text("Apache-2.0".into()));
+        assert_eq!(
+            values["features"],
+            Value::List(vec!["docker".into(), "ci".into()])
+        );
+        assert_eq!(values["git"], Value::Bool(true));
+    }
+
+    #[test]
+    fn test_reject_invalid_values() {
+        let variables = variables();
+        assert!(variables[0].parse("My App").is_err());
+        assert!(variables[1].parse("GPL").is_err());
+        assert!(variables[2].parse("docker,k8s").is_err());
+        assert!(collect(&variables[..1], &["nope=1".into(), "project_name=a".into()]).is_err());
+
+        let bad_default = Variable {
+            kind: Prompt::Select {
+                options: vec!["a".into()],
+                default: Some("b".into()),
+            },
+            ..variables[1].clone()
+        };
+        assert!(bad_default.check().is_err());
+    }
 }
@@ -0,0 +1,631 @@
+//! Small templating language used to render files with the answers
+//! to a template's variables
+//!
+//! `{{ name }}` outputs a variable, optionally passed through filters
+//! like `{{ name | snake_case }}`. `{% if expr %}`, `{% elif expr %}`,
+//! `{% else %}` and `{% endif %}` pick a section, and
+//! `{% for item in list %}` ... `{% endfor %}` repeats one. A block tag
+//! alone on its line takes the line with it when rendered.
+//!
+//! Expressions are variables, "strings", `true` and `false`, combined
+//! with `==`, `!=`, `in`, `not`, `and`, `or` and parentheses.
+
+use super::prompt::{Value, Variables};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Eq,
+    Ne,
+    Pipe,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '|' => tokens.push(Token::Pipe),
+            '=' | '!' if chars.peek() == Some(&'=') => {
+                chars.next();
+                tokens.push(if c == '=' { Token::Eq } else { Token::Ne });
+            }
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err("Unterminated string".into()),
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&ch) = chars
+                    .peek()
+                    .filter(|ch| ch.is_alphanumeric() || **ch == '_')
+                {
+                    ident.push(ch);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => return Err(format!("Unexpected \"{}\"", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// A parsed expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Var(String),
+    Str(String),
+    Bool(bool),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Box<Expr>),
+}
+
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Ident(word.into())) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.keyword("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.keyword("and") {
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = Box::new(self.atom()?);
+        if self.keyword("in") {
+            return Ok(Expr::In(left, Box::new(self.atom()?)));
+        }
+        match self.peek() {
+            Some(Token::Eq) => {
+                self.pos += 1;
+                Ok(Expr::Eq(left, Box::new(self.atom()?)))
+            }
+            Some(Token::Ne) => {
+                self.pos += 1;
+                Ok(Expr::Ne(left, Box::new(self.atom()?)))
+            }
+            _ => Ok(*left),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    _ => Err("Expected \")\"".into()),
+                }
+            }
+            Some(Token::Str(text)) => Ok(Expr::Str(text)),
+            Some(Token::Ident(word)) => match word.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "and" | "or" | "not" | "in" => Err(format!("Unexpected \"{}\"", word)),
+                _ => Ok(Expr::Var(word)),
+            },
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Expected an expression".into()),
+        }
+    }
+}
+
+/// Parse an expression like `database == "postgres" and "ci" in features`
+pub fn parse_expr(source: &str) -> Result<Expr, String> {
+    let (expr, filters) = parse_output(source)?;
+    if !filters.is_empty() {
+        return Err("Filters can't be used in conditions".into());
+    }
+    Ok(expr)
+}
+
+/// Parse the inside of `{{ }}`: an expression followed by filters
+fn parse_output(source: &str) -> Result<(Expr, Vec<String>), String> {
+    let mut parser = ExprParser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    let expr = parser.or()?;
+    let mut filters = Vec::new();
+    while parser.peek() == Some(&Token::Pipe) {
+        parser.pos += 1;
+        match parser.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                parser.pos += 1;
+                filters.push(name);
+            }
+            _ => return Err("Expected a filter name after \"|\"".into()),
+        }
+    }
+    if let Some(token) = parser.peek() {
+        return Err(format!("Unexpected {:?}", token));
+    }
+    Ok((expr, filters))
+}
+
+impl Expr {
+    /// Evaluate against the given answers
+    pub fn eval(&self, vars: &Variables) -> Result<Value, String> {
+        let bool = |expr: &Expr| Ok::<bool, String>(truthy(&expr.eval(vars)?));
+        Ok(match self {
+            Expr::Var(name) => vars
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Unknown variable \"{}\"", name))?,
+            Expr::Str(text) => Value::Text(text.clone()),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Not(expr) => Value::Bool(!bool(expr)?),
+            Expr::And(a, b) => Value::Bool(bool(a)? && bool(b)?),
+            Expr::Or(a, b) => Value::Bool(bool(a)? || bool(b)?),
+            Expr::Eq(a, b) => Value::Bool(a.eval(vars)? == b.eval(vars)?),
+            Expr::Ne(a, b) => Value::Bool(a.eval(vars)? != b.eval(vars)?),
+            Expr::In(a, b) => {
+                let needle = a.eval(vars)?.to_string();
+                Value::Bool(match b.eval(vars)? {
+                    Value::List(items) => items.contains(&needle),
+                    Value::Text(text) => text.contains(&needle),
+                    Value::Bool(_) => return Err("\"in\" needs a list or text".into()),
+                })
+            }
+        })
+    }
+
+    /// Names of the variables the expression refers to
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expr::Var(name) => vec![name.as_str()],
+            Expr::Str(_) | Expr::Bool(_) => Vec::new(),
+            Expr::Not(expr) => expr.variables(),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Eq(a, b) | Expr::Ne(a, b) | Expr::In(a, b) => {
+                let mut names = a.variables();
+                names.extend(b.variables());
+                names
+            }
+        }
+    }
+
+    /// Evaluate as a condition
+    pub fn eval_bool(&self, vars: &Variables) -> Result<bool, String> {
+        Ok(truthy(&self.eval(vars)?))
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Text(text) => !text.is_empty(),
+        Value::List(items) => !items.is_empty(),
+        Value::Bool(b) => *b,
+    }
+}
+
+/// Split an identifier-like string into lowercase words, breaking on
+/// separators and case changes
+fn words(text: &str) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        // break before "Case" in "camelCase" and "HTTPServer"
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_numeric()
+                    || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase()))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn apply_filter(name: &str, text: &str) -> Result<String, String> {
+    Ok(match name {
+        "snake_case" => words(text).join("_"),
+        "kebab_case" => words(text).join("-"),
+        "PascalCase" => words(text).iter().map(|w| capitalize(w)).collect(),
+        "camelCase" => {
+            let pascal = words(text)
+                .iter()
+                .map(|w| capitalize(w))
+                .collect::<String>();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+        "upper" => text.to_uppercase(),
+        "lower" => text.to_lowercase(),
+        _ => return Err(format!("Unknown filter \"{}\"", name)),
+    })
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output {
+        expr: Expr,
+        filters: Vec<String>,
+        line: usize,
+    },
+    If {
+        branches: Vec<(Expr, Vec<Node>)>,
+        otherwise: Vec<Node>,
+        line: usize,
+    },
+    For {
+        item: String,
+        list: Expr,
+        body: Vec<Node>,
+        line: usize,
+    },
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Output(&'a str, usize),
+    Tag(&'a str, usize),
+}
+
+/// Split source into text, `{{ }}` and `{% %}` pieces
+fn lex(source: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = source;
+    let line_of = |rest: &str| source[..source.len() - rest.len()].matches('\n').count() + 1;
+    loop {
+        let start = match (rest.find("{{"), rest.find("{%")) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => break,
+        };
+        let (text, tag) = rest.split_at(start);
+        let line = line_of(tag);
+        let (close, is_output) = if tag.starts_with("{{") {
+            ("}}", true)
+        } else {
+            ("%}", false)
+        };
+        let end = tag[2..]
+            .find(close)
+            .ok_or_else(|| format!("line {}: \"{}\" is never closed", line, &tag[..2]))?;
+        let inner = tag[2..2 + end].trim();
+        rest = &tag[2 + end + 2..];
+
+        if is_output {
+            pieces.push(Piece::Text(text));
+            pieces.push(Piece::Output(inner, line));
+            continue;
+        }
+        // a block tag alone on its line takes the whole line with it
+        let line_start = text.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let after = rest.find('\n').map(|i| i + 1).unwrap_or(rest.len());
+        if text[line_start..].trim().is_empty() && rest[..after].trim().is_empty() {
+            pieces.push(Piece::Text(&text[..line_start]));
+            rest = &rest[after..];
+        } else {
+            pieces.push(Piece::Text(text));
+        }
+        pieces.push(Piece::Tag(inner, line));
+    }
+    pieces.push(Piece::Text(rest));
+    Ok(pieces)
+}
+
+/// Nodes of a section, along with the tag that ended it and its line
+type Section = (Vec<Node>, Option<(String, usize)>);
+
+struct Parser<'a> {
+    pieces: std::vec::IntoIter<Piece<'a>>,
+}
+
+impl Parser<'_> {
+    /// Parse nodes up to one of the `until` tags, returning the tag that
+    /// ended the section
+    fn nodes(&mut self, until: &[&str]) -> Result<Section, String> {
+        let mut nodes = Vec::new();
+        while let Some(piece) = self.pieces.next() {
+            match piece {
+                Piece::Text("") => {}
+                Piece::Text(text) => nodes.push(Node::Text(text.to_owned())),
+                Piece::Output(inner, line) => {
+                    let (expr, filters) =
+                        parse_output(inner).map_err(|e| format!("line {}: {}", line, e))?;
+                    nodes.push(Node::Output {
+                        expr,
+                        filters,
+                        line,
+                    });
+                }
+                Piece::Tag(inner, line) => {
+                    let at = |e: String| format!("line {}: {}", line, e);
+                    let (keyword, rest) =
+                        inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+                    match keyword {
+                        "if" => nodes.push(self.if_block(rest, line).map_err(at)?),
+                        "for" => nodes.push(self.for_block(rest, line).map_err(at)?),
+                        keyword if until.contains(&keyword) => {
+                            return Ok((nodes, Some((inner.to_owned(), line))))
+                        }
+                        _ => return Err(at(format!("Unexpected \"{{% {} %}}\"", inner))),
+                    }
+                }
+            }
+        }
+        Ok((nodes, None))
+    }
+
+    fn if_block(&mut self, condition: &str, line: usize) -> Result<Node, String> {
+        let mut branches = Vec::new();
+        let mut condition = parse_expr(condition)?;
+        loop {
+            let (body, end) = self.nodes(&["elif", "else", "endif"])?;
+            branches.push((condition, body));
+            let (tag, tag_line) = end.ok_or("\"{% if %}\" is never closed")?;
+            let (keyword, rest) = tag.split_once(char::is_whitespace).unwrap_or((&tag, ""));
+            match keyword {
+                "elif" => {
+                    condition = parse_expr(rest).map_err(|e| format!("line {}: {}", tag_line, e))?
+                }
+                "else" => {
+                    let (otherwise, end) = self.nodes(&["endif"])?;
+                    end.ok_or("\"{% if %}\" is never closed")?;
+                    return Ok(Node::If {
+                        branches,
+                        otherwise,
+                        line,
+                    });
+                }
+                _ => {
+                    return Ok(Node::If {
+                        branches,
+                        otherwise: Vec::new(),
+                        line,
+                    })
+                }
+            }
+        }
+    }
+
+    fn for_block(&mut self, header: &str, line: usize) -> Result<Node, String> {
+        let (item, list) = match header.split_once(" in ") {
+            Some((item, list)) if !item.trim().is_empty() => (item.trim(), list),
+            _ => return Err("Expected \"{% for item in list %}\"".into()),
+        };
+        let list = parse_expr(list)?;
+        let (body, end) = self.nodes(&["endfor"])?;
+        end.ok_or("\"{% for %}\" is never closed")?;
+        Ok(Node::For {
+            item: item.to_owned(),
+            list,
+            body,
+            line,
+        })
+    }
+}
+
+fn render_nodes(nodes: &[Node], vars: &Variables, out: &mut String) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output {
+                expr,
+                filters,
+                line,
+            } => {
+                let at = |e: String| format!("line {}: {}", line, e);
+                let mut text = expr.eval(vars).map_err(at)?.to_string();
+                for filter in filters {
+                    text = apply_filter(filter, &text).map_err(at)?;
+                }
+                out.push_str(&text);
+            }
+            Node::If {
+                branches,
+                otherwise,
+                line,
+            } => {
+                let mut chosen = otherwise;
+                for (condition, body) in branches {
+                    if condition
+                        .eval_bool(vars)
+                        .map_err(|e| format!("line {}: {}", line, e))?
+                    {
+                        chosen = body;
+                        break;
+                    }
+                }
+                render_nodes(chosen, vars, out)?;
+            }
+            Node::For {
+                item,
+                list,
+                body,
+                line,
+            } => {
+                let items = match list.eval(vars) {
+                    Ok(Value::List(items)) => items,
+                    Ok(_) => return Err(format!("line {}: Only lists can be looped over", line)),
+                    Err(e) => return Err(format!("line {}: {}", line, e)),
+                };
+                let mut scope = vars.clone();
+                for value in items {
+                    scope.insert(item.clone(), Value::Text(value));
+                    render_nodes(body, &scope, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render a template with the given answers
+pub fn render(source: &str, vars: &Variables) -> Result<String, String> {
+    let mut parser = Parser {
+        pieces: lex(source)?.into_iter(),
+    };
+    let (nodes, end) = parser.nodes(&[])?;
+    if let Some((tag, line)) = end {
+        return Err(format!("line {}: Unexpected \"{{% {} %}}\"", line, tag));
+    }
+    let mut out = String::new();
+    render_nodes(&nodes, vars, &mut out)?;
+    Ok(out)
+}
+
+/// Whether content looks like a binary file, which is never rendered
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(8000)].contains(&0) || std::str::from_utf8(content).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Variables {
+        Variables::from([
+            ("project_name".into(), Value::Text("my epic-App".into())),
+            ("database".into(), Value::Text("postgres".into())),
+            (
+                "features".into(),
+                Value::List(vec!["docker".into(), "ci".into()]),
+            ),
+            ("git".into(), Value::Bool(false)),
+        ])
+    }
+
+    #[test]
+    fn test_render_filters() {
+        let out = render(
+            "{{ project_name }} {{project_name|snake_case}} {{ project_name | kebab_case }} \
+             {{ project_name | PascalCase }} {{ project_name | camelCase | upper }}",
+            &vars(),
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "my epic-App my_epic_app my-epic-app MyEpicApp MYEPICAPP"
+        );
+        assert_eq!(words("HTTPServer2Go"), ["http", "server2", "go"]);
+    }
+
+    #[test]
+    fn test_render_blocks() {
+        let source = "\
+services:
+{% for feature in features %}
+  - {{ feature }}
+{% endfor %}
+{% if database == \"sqlite\" %}
+sqlite
+{% elif \"ci\" in features and not git %}
+ci without git
+{% else %}
+neither
+{% endif %}
+done {% if git %}git{% endif %}
+";
+        assert_eq!(
+            render(source, &vars()).unwrap(),
+            "services:\n  - docker\n  - ci\nci without git\ndone \n"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        for source in [
+            "{{ missing }}",
+            "{{ project_name | shout }}",
+            "{% if git %}never closed",
+            "line one\n{{ unclosed",
+            "{% endif %}",
+            "{% for x in database %}{% endfor %}",
+        ] {
+            assert!(render(source, &vars()).is_err(), "{}", source);
+        }
+        assert!(render("a\n{{ missing }}", &vars())
+            .unwrap_err()
+            .starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(is_binary(&[0x89, b'P', b'N', b'G', 0, 0]));
+        assert!(!is_binary("{{ project_name }}".as_bytes()));
+    }
+}
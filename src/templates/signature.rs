@@ -0,0 +1,209 @@
+//! Ed25519 signatures over the archive digest of a bowlfile
+//!
+//! Keys are kept on disk as hex. A signing key file holds the 32 byte
+//! secret seed and the matching `.pub` file holds the public key.
+
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use super::digest::{to_hex, Digest, HashingReader};
+use super::symbols::CURRENT_VERSION;
+use super::versions::read_version;
+use super::versions::v0_1_0::{DIGEST_LEN, FOOTER_LEN, HEADER_LEN, SIGNATURE_LEN};
+use crate::files::trusted_key_paths;
+
+/// Public key and signature stored in the footer of a signed bowlfile
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureBlock {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl SignatureBlock {
+    /// Sign the archive digest of a bowlfile
+    pub fn sign(key: &SigningKey, digest: &Digest) -> Self {
+        Self {
+            public_key: key.verifying_key().to_bytes(),
+            signature: key.sign(digest).to_bytes(),
+        }
+    }
+
+    /// Bytes as stored in the footer
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_LEN] {
+        let mut bytes = [0; SIGNATURE_LEN];
+        bytes[..32].copy_from_slice(&self.public_key);
+        bytes[32..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    /// Parse a stored block. Unsigned bowlfiles store zeros, giving `None`.
+    pub fn from_bytes(bytes: &[u8; SIGNATURE_LEN]) -> Option<Self> {
+        if bytes.iter().all(|b| *b == 0) {
+            return None;
+        }
+        Some(Self {
+            public_key: bytes[..32].try_into().unwrap(),
+            signature: bytes[32..].try_into().unwrap(),
+        })
+    }
+
+    /// Check the signature against the archive digest, returning the key
+    /// that made it
+    pub fn verify(&self, digest: &Digest) -> Result<VerifyingKey, String> {
+        let key = VerifyingKey::from_bytes(&self.public_key)
+            .map_err(|_| "Bowl file is signed with an invalid public key".to_owned())?;
+        key.verify_strict(digest, &Signature::from_bytes(&self.signature))
+            .map_err(|_| "Bowl file has an invalid signature".to_owned())?;
+        Ok(key)
+    }
+}
+
+/// Make a new signing key from the operating system's randomness
+pub fn generate_signing_key() -> Result<SigningKey, String> {
+    let mut seed = [0; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| format!("Failed to generate key: {}", e))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+fn read_key_bytes(path: &Path) -> Result<[u8; 32], String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read key {}: {}", path.display(), e))?;
+    hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("{} is not a valid key file", path.display()))
+}
+
+/// Read a hex encoded signing key
+pub fn read_signing_key(path: &Path) -> Result<SigningKey, String> {
+    Ok(SigningKey::from_bytes(&read_key_bytes(path)?))
+}
+
+/// Read a hex encoded public key
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey, String> {
+    VerifyingKey::from_bytes(&read_key_bytes(path)?)
+        .map_err(|_| format!("{} is not a valid public key", path.display()))
+}
+
+/// Read the signature block of the bowlfile in `inner`, after checking
+/// that the archive digest it signs matches the rest of the file. Returns
+/// `None` for bowlfiles that aren't signed.
+pub fn read_signature<R: Read + Seek>(inner: &mut R) -> Result<Option<SignatureBlock>, String> {
+    let read_err = |e: io::Error| format!("Failed to read bowlfile: {}", e);
+    let len = inner.seek(SeekFrom::End(0)).map_err(read_err)?;
+    let mut header = [0; HEADER_LEN];
+    inner.seek(SeekFrom::Start(0)).map_err(read_err)?;
+    inner
+        .read_exact(&mut header)
+        .map_err(|_| "Invalid bowl file provided".to_owned())?;
+    // legacy bowlfiles have nowhere to keep a signature
+    if read_version(&header)? != CURRENT_VERSION {
+        return Ok(None);
+    }
+    if len < (HEADER_LEN + FOOTER_LEN) as u64 {
+        return Err("Unexpected end of bowl file".into());
+    }
+
+    inner.seek(SeekFrom::Start(0)).map_err(read_err)?;
+    let covered = len - (DIGEST_LEN + SIGNATURE_LEN) as u64;
+    let mut hashing = HashingReader::new(inner.by_ref().take(covered));
+    io::copy(&mut hashing, &mut io::sink()).map_err(read_err)?;
+    let actual = hashing.finalize();
+
+    let mut expected: Digest = [0; DIGEST_LEN];
+    let mut block = [0; SIGNATURE_LEN];
+    inner.read_exact(&mut expected).map_err(read_err)?;
+    inner.read_exact(&mut block).map_err(read_err)?;
+    if actual != expected {
+        return Err("Bowl file is damaged: archive digest mismatch".into());
+    }
+
+    match SignatureBlock::from_bytes(&block) {
+        Some(signature) => {
+            signature.verify(&expected)?;
+            Ok(Some(signature))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Name a public key is trusted under, if it is trusted
+pub fn trusted_name(public_key: &[u8; 32]) -> Result<Option<String>, String> {
+    for p in trusted_key_paths()? {
+        if read_verifying_key(&p)?.to_bytes() == *public_key {
+            return Ok(Some(p.file_stem().unwrap().to_string_lossy().into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Make sure the bowlfile is intact and signed by a trusted key before
+/// anything in it is used, returning the name the key is trusted under
+pub fn trusted_signer<R: Read + Seek>(inner: &mut R) -> Result<String, String> {
+    let signature = read_signature(inner)?.ok_or(
+        "Template is not signed\nOnly templates signed by a trusted key can be used \
+        (pass --allow-unsigned to use it anyway)",
+    )?;
+    if let Some(name) = trusted_name(&signature.public_key)? {
+        return Ok(name);
+    }
+    Err(format!(
+        "Template is signed by an unknown key {}\nIf you trust its author, \
+        add their public key with `bowl trust <key>`",
+        to_hex(&signature.public_key)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::compression::Compression;
+    use crate::templates::files::FileContent;
+    use crate::templates::stream::BowlWriter;
+    use std::io::Cursor;
+
+    fn build(key: Option<SigningKey>) -> Vec<u8> {
+        let mut writer = BowlWriter::new(Vec::new(), Compression::Zstd).unwrap();
+        if let Some(key) = key {
+            writer = writer.sign_with(key);
+        }
+        writer
+            .write_file(&FileContent::new("./main.go".into(), "package main".into()))
+            .unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let raw = build(Some(key.clone()));
+
+        let signature = read_signature(&mut Cursor::new(&raw)).unwrap().unwrap();
+        assert_eq!(signature.public_key, key.verifying_key().to_bytes());
+
+        assert_eq!(read_signature(&mut Cursor::new(build(None))).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reject_tampered_signature() {
+        let raw = build(Some(SigningKey::from_bytes(&[7; 32])));
+
+        // content changed after signing
+        let mut tampered = raw.clone();
+        let pos = tampered.windows(7).position(|w| w == b"./main.").unwrap();
+        tampered[pos + 2] = b'M';
+        assert!(read_signature(&mut Cursor::new(tampered)).is_err());
+
+        // signature swapped for one by another key
+        let mut forged = raw.clone();
+        let len = forged.len();
+        let other = SignatureBlock::sign(&SigningKey::from_bytes(&[8; 32]), &[0; 32]);
+        forged[len - SIGNATURE_LEN..].copy_from_slice(&other.to_bytes());
+        assert!(read_signature(&mut Cursor::new(forged)).is_err());
+    }
+}
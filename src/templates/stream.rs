@@ -0,0 +1,689 @@
+//! Streaming access to bowlfiles
+//!
+//! `BowlWriter` and `BowlReader` handle one entry at a time so that
+//! publishing or using a template never needs the whole thing in memory.
+//! See `versions::v0_1_0` for the layout they produce and consume.
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bowl_core::config::Config;
+use ed25519_dalek::SigningKey;
+
+use super::bowlfile::{parse_config, BowlFile};
+use super::compression::Compression;
+use super::digest::{Digest, HashingReader, HashingWriter};
+use super::files::{check_entry, EntryKind, FileContent};
+use super::signature::SignatureBlock;
+use super::symbols::{
+    BOWL_CHAR, CURRENT_VERSION, FILE_CHAR, LEGACY_VERSION, TABLE_CHAR, VERSION_CHAR,
+};
+use super::versions::v0_1_0::{TableEntry, DIGEST_LEN, FOOTER_LEN, SIGNATURE_LEN};
+use crate::files::file_mode;
+
+/// Metadata of the entry a `BowlReader` is currently positioned on
+#[derive(Debug, Clone)]
+pub struct EntryHeader {
+    pub file_path: String,
+    pub kind: EntryKind,
+    pub mode: u32,
+    /// Length of the payload as stored, after compression
+    pub length: u64,
+}
+
+/// Compressed payloads of files up to this size are buffered in memory,
+/// larger ones are spilled to a temp file
+const MAX_BUFFERED: u64 = 4 * 1024 * 1024;
+
+/// Temp file holding a compressed payload until its length is known.
+/// It is removed when dropped.
+struct Spill {
+    path: PathBuf,
+    file: File,
+}
+
+impl Spill {
+    fn new() -> Result<Self, String> {
+        static COUNT: AtomicU64 = AtomicU64::new(0);
+        let path = env::temp_dir().join(format!(
+            "bowl-{}-{}.spill",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to create temp file: {}", e))?;
+        Ok(Self { path, file })
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Writes a bowlfile entry by entry
+///
+/// Uncompressed payloads are copied straight through. Compressed ones
+/// have to be measured before they are written, so small ones are
+/// buffered in memory and large ones in a temp file.
+pub struct BowlWriter<W: Write> {
+    inner: HashingWriter<W>,
+    compression: Compression,
+    written: u64,
+    table: Vec<TableEntry>,
+    signing_key: Option<SigningKey>,
+}
+
+impl<W: Write> BowlWriter<W> {
+    /// Start a new bowlfile, writing the header to `inner`
+    pub fn new(inner: W, compression: Compression) -> Result<Self, String> {
+        let mut writer = Self {
+            inner: HashingWriter::new(inner),
+            compression,
+            written: 0,
+            table: Vec::new(),
+            signing_key: None,
+        };
+        writer.put(&[BOWL_CHAR, VERSION_CHAR])?;
+        writer.put(CURRENT_VERSION.as_bytes())?;
+        writer.put(&[compression.to_byte()])?;
+        Ok(writer)
+    }
+
+    /// Sign the finished bowlfile with `key`
+    pub fn sign_with(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.inner
+            .write_all(bytes)
+            .map_err(|e| format!("Failed to write bowlfile: {}", e))?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn put_path(&mut self, path: &str) -> Result<(), String> {
+        let len = u16::try_from(path.len()).map_err(|_| format!("File name too long: {}", path))?;
+        self.put(&len.to_le_bytes())?;
+        self.put(path.as_bytes())
+    }
+
+    fn put_meta(&mut self, file_path: &str, kind: &EntryKind, mode: u32) -> Result<(), String> {
+        self.put(&[kind.to_byte()])?;
+        self.put_path(file_path)?;
+        self.put(&mode.to_le_bytes())?;
+        self.put_path(kind.target())
+    }
+
+    fn put_entry_header(
+        &mut self,
+        file_path: &str,
+        kind: &EntryKind,
+        mode: u32,
+        length: u64,
+    ) -> Result<(), String> {
+        self.put(&[FILE_CHAR])?;
+        self.put_meta(file_path, kind, mode)?;
+        self.put(&length.to_le_bytes())
+    }
+
+    /// Write the digest that closes an entry whose `length` byte payload
+    /// was just written, and record the entry in the table
+    fn put_entry_digest(
+        &mut self,
+        file_path: &str,
+        kind: &EntryKind,
+        mode: u32,
+        length: u64,
+        digest: Digest,
+    ) -> Result<(), String> {
+        let offset = self.written - length;
+        self.put(&digest)?;
+        self.table.push(TableEntry {
+            file_path: file_path.to_owned(),
+            kind: kind.clone(),
+            mode,
+            digest,
+            offset,
+            length,
+        });
+        Ok(())
+    }
+
+    /// Add an entry whose `length` bytes of content are read from `content`.
+    /// Directories and symlinks have no content.
+    pub fn write_entry<R: Read>(
+        &mut self,
+        file_path: &str,
+        kind: &EntryKind,
+        mode: u32,
+        length: u64,
+        content: R,
+    ) -> Result<(), String> {
+        let mut content = HashingReader::new(content.take(length));
+        let length = match self.compression {
+            _ if *kind != EntryKind::File => {
+                self.put_entry_header(file_path, kind, mode, 0)?;
+                0
+            }
+            Compression::None => {
+                self.put_entry_header(file_path, kind, mode, length)?;
+                let copied = io::copy(&mut content, &mut self.inner)
+                    .map_err(|e| format!("Failed to write \"{}\": {}", file_path, e))?;
+                self.written += copied;
+                if copied != length {
+                    return Err(format!("\"{}\" changed while it was read", file_path));
+                }
+                length
+            }
+            compression if length <= MAX_BUFFERED => {
+                let mut payload = Vec::new();
+                compression.compress_to(&mut content, &mut payload)?;
+                self.put_entry_header(file_path, kind, mode, payload.len() as u64)?;
+                self.put(&payload)?;
+                payload.len() as u64
+            }
+            compression => {
+                let spill_err =
+                    |e: io::Error| format!("Failed to compress \"{}\": {}", file_path, e);
+                let mut spill = Spill::new()?;
+                let mut out = BufWriter::new(&spill.file);
+                compression.compress_to(&mut content, &mut out)?;
+                out.flush().map_err(spill_err)?;
+                drop(out);
+                let compressed = spill.file.stream_position().map_err(spill_err)?;
+                spill.file.seek(SeekFrom::Start(0)).map_err(spill_err)?;
+                self.put_entry_header(file_path, kind, mode, compressed)?;
+                let copied = io::copy(&mut BufReader::new(&spill.file), &mut self.inner)
+                    .map_err(|e| format!("Failed to write \"{}\": {}", file_path, e))?;
+                self.written += copied;
+                if copied != compressed {
+                    return Err(format!(
+                        "Failed to write \"{}\": temp file was cut short",
+                        file_path
+                    ));
+                }
+                compressed
+            }
+        };
+        // a compressed file that came up short wouldn't be noticed otherwise
+        if content.get_mut().limit() != 0 {
+            return Err(format!("\"{}\" changed while it was read", file_path));
+        }
+        self.put_entry_digest(file_path, kind, mode, length, content.finalize())
+    }
+
+    /// Add an entry read straight from the filesystem at `path`.
+    /// Symlinks are stored as links rather than followed.
+    pub fn write_path(&mut self, path: &Path) -> Result<(), String> {
+        let file_path = path
+            .to_str()
+            .ok_or_else(|| format!("File name is not valid UTF-8: {}", path.display()))?
+            .to_owned();
+        let metadata = fs::symlink_metadata(path)
+            .map_err(|e| format!("Failed to read \"{}\": {}", file_path, e))?;
+        let mode = file_mode(&metadata);
+        if metadata.is_symlink() {
+            let target = fs::read_link(path)
+                .map_err(|e| format!("Failed to read link \"{}\": {}", file_path, e))?;
+            let target = target.to_str().ok_or_else(|| {
+                format!(
+                    "Link target of \"{}\" is not valid UTF-8: {}",
+                    file_path,
+                    target.display()
+                )
+            })?;
+            let kind = EntryKind::Symlink {
+                target: target.to_owned(),
+            };
+            self.write_entry(&file_path, &kind, mode, 0, io::empty())
+        } else if metadata.is_dir() {
+            self.write_entry(&file_path, &EntryKind::Directory, mode, 0, io::empty())
+        } else {
+            let file =
+                File::open(path).map_err(|e| format!("Failed to open \"{}\": {}", file_path, e))?;
+            self.write_entry(
+                &file_path,
+                &EntryKind::File,
+                mode,
+                metadata.len(),
+                BufReader::new(file),
+            )
+        }
+    }
+
+    /// Add an entry that is already in memory
+    pub fn write_file(&mut self, file: &FileContent) -> Result<(), String> {
+        self.write_entry(
+            &file.file_path,
+            &file.kind,
+            file.mode,
+            file.content.len() as u64,
+            &file.content[..],
+        )
+    }
+
+    /// Write the file table and footer, signing the archive digest if a
+    /// key was given, and return the underlying writer
+    pub fn finish(mut self) -> Result<W, String> {
+        let table_offset = self.written;
+        let table = std::mem::take(&mut self.table);
+        self.put(&[TABLE_CHAR])?;
+        self.put(&(table.len() as u32).to_le_bytes())?;
+        for entry in table {
+            self.put_meta(&entry.file_path, &entry.kind, entry.mode)?;
+            self.put(&entry.digest)?;
+            self.put(&entry.offset.to_le_bytes())?;
+            self.put(&entry.length.to_le_bytes())?;
+        }
+        self.put(&table_offset.to_le_bytes())?;
+        let archive_digest = self.inner.finalize();
+        self.put(&archive_digest)?;
+        let signature = match self.signing_key.take() {
+            Some(key) => SignatureBlock::sign(&key, &archive_digest).to_bytes(),
+            None => [0; SIGNATURE_LEN],
+        };
+        self.put(&signature)?;
+        self.inner
+            .flush()
+            .map_err(|e| format!("Failed to write bowlfile: {}", e))?;
+        Ok(self.inner.into_inner())
+    }
+}
+
+enum Source<R: Read> {
+    /// A current bowlfile read front to back
+    Stream {
+        inner: HashingReader<R>,
+        /// Payload bytes of the current entry that haven't been read
+        remaining: u64,
+        /// Whether the digest closing the current entry is still unread
+        digest_pending: bool,
+        /// Whether the end of the entries has been reached
+        finished: bool,
+    },
+    /// A legacy bowlfile that had to be decoded up front
+    Memory {
+        files: std::vec::IntoIter<FileContent>,
+        current: Vec<u8>,
+    },
+}
+
+/// Reads a bowlfile entry by entry
+///
+/// Call `next_entry` to move to the next file, then `copy_content` or
+/// `read_content` to get at its content. Content that isn't read is
+/// skipped. Content that is read is checked against its digest, and the
+/// archive digest is checked once the last entry has been passed.
+pub struct BowlReader<R: Read> {
+    pub version: String,
+    pub compression: Compression,
+    source: Source<R>,
+    entry_path: String,
+}
+
+fn read_bytes<R: Read>(inner: &mut R, n: usize) -> Result<Vec<u8>, String> {
+    let mut buf = vec![0; n];
+    inner
+        .read_exact(&mut buf)
+        .map_err(|_| "Unexpected end of bowl file".to_owned())?;
+    Ok(buf)
+}
+
+fn read_array<R: Read, const N: usize>(inner: &mut R) -> Result<[u8; N], String> {
+    let mut buf = [0; N];
+    inner
+        .read_exact(&mut buf)
+        .map_err(|_| "Unexpected end of bowl file".to_owned())?;
+    Ok(buf)
+}
+
+fn read_path<R: Read>(inner: &mut R) -> Result<String, String> {
+    let len = u16::from_le_bytes(read_array(inner)?) as usize;
+    String::from_utf8(read_bytes(inner, len)?)
+        .map_err(|e| format!("Invalid file name in bowl file: {}", e))
+}
+
+impl<R: Read> BowlReader<R> {
+    /// Read the header of the bowlfile in `inner`. Legacy bowlfiles
+    /// can't be streamed, so they are decoded into memory right away.
+    pub fn new(mut inner: R) -> Result<Self, String> {
+        let mut header = read_bytes(&mut inner, 2 + CURRENT_VERSION.len())?;
+        let version = match std::str::from_utf8(&header[2..]) {
+            Ok(version) if header[..2] == [BOWL_CHAR, VERSION_CHAR] => version.to_owned(),
+            _ => return Err("Invalid bowl file provided".into()),
+        };
+
+        match version.as_str() {
+            CURRENT_VERSION => {
+                let mut inner = HashingReader::new(inner);
+                inner.update(&header);
+                let [codec] = read_array(&mut inner)?;
+                Ok(Self {
+                    version,
+                    compression: Compression::from_byte(codec)?,
+                    source: Source::Stream {
+                        inner,
+                        remaining: 0,
+                        digest_pending: false,
+                        finished: false,
+                    },
+                    entry_path: String::new(),
+                })
+            }
+            LEGACY_VERSION => {
+                inner
+                    .read_to_end(&mut header)
+                    .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+                let mut bf = BowlFile::decode(header)?;
+                // the config is expected up front, like in current bowlfiles
+                bf.files.sort_by_key(|f| f.file_path != "./bowl.toml");
+                Ok(Self {
+                    version,
+                    compression: bf.compression,
+                    source: Source::Memory {
+                        files: bf.files.into_iter(),
+                        current: Vec::new(),
+                    },
+                    entry_path: String::new(),
+                })
+            }
+            v => Err(format!("Unsupported bowl file version: {}", v)),
+        }
+    }
+
+    /// Move to the next entry, skipping whatever is left of the current one.
+    /// Returns `None` once every entry has been read.
+    pub fn next_entry(&mut self) -> Result<Option<EntryHeader>, String> {
+        let entry = match &mut self.source {
+            Source::Stream { finished: true, .. } => None,
+            Source::Stream {
+                inner,
+                remaining,
+                digest_pending,
+                finished,
+            } => {
+                let skip = *remaining
+                    + if *digest_pending {
+                        DIGEST_LEN as u64
+                    } else {
+                        0
+                    };
+                io::copy(&mut inner.by_ref().take(skip), &mut io::sink())
+                    .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+                *remaining = 0;
+                *digest_pending = false;
+                match read_array(inner)? {
+                    [FILE_CHAR] => {
+                        let [kind] = read_array(inner)?;
+                        let file_path = read_path(inner)?;
+                        let mode = u32::from_le_bytes(read_array(inner)?);
+                        let kind = EntryKind::from_byte(kind, read_path(inner)?)?;
+                        check_entry(&file_path, &kind)?;
+                        let length = u64::from_le_bytes(read_array(inner)?);
+                        *remaining = length;
+                        *digest_pending = true;
+                        Some(EntryHeader {
+                            file_path,
+                            kind,
+                            mode,
+                            length,
+                        })
+                    }
+                    [TABLE_CHAR] => {
+                        *finished = true;
+                        verify_archive(inner)?;
+                        None
+                    }
+                    _ => return Err("Invalid entry in bowl file".into()),
+                }
+            }
+            Source::Memory { files, current } => files.next().map(|f| {
+                *current = f.content;
+                EntryHeader {
+                    file_path: f.file_path,
+                    kind: f.kind,
+                    mode: f.mode,
+                    length: current.len() as u64,
+                }
+            }),
+        };
+        if let Some(entry) = &entry {
+            self.entry_path.clone_from(&entry.file_path);
+        }
+        Ok(entry)
+    }
+
+    /// Decompress the content of the current entry into `out`, checking
+    /// it against the entry's digest
+    pub fn copy_content<W: Write>(&mut self, out: &mut W) -> Result<u64, String> {
+        match &mut self.source {
+            Source::Stream {
+                digest_pending: false,
+                ..
+            } => Ok(0),
+            Source::Stream {
+                inner,
+                remaining,
+                digest_pending,
+                ..
+            } => {
+                let mut out = HashingWriter::new(out);
+                let mut payload = inner.by_ref().take(*remaining);
+                // directories and symlinks have nothing stored at all
+                let copied = match *remaining {
+                    0 => 0,
+                    _ => io::copy(&mut self.compression.decoder(&mut payload)?, &mut out)
+                        .map_err(|e| format!("\"{}\" is damaged: {}", self.entry_path, e))?,
+                };
+                io::copy(&mut payload, &mut io::sink())
+                    .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+                *remaining = 0;
+                *digest_pending = false;
+                let expected: Digest = read_array(inner)?;
+                if out.finalize() != expected {
+                    return Err(format!(
+                        "\"{}\" is damaged: checksum mismatch",
+                        self.entry_path
+                    ));
+                }
+                Ok(copied)
+            }
+            Source::Memory { current, .. } => {
+                let content = std::mem::take(current);
+                out.write_all(&content)
+                    .map_err(|e| format!("Failed to read bowlfile entry: {}", e))?;
+                Ok(content.len() as u64)
+            }
+        }
+    }
+
+    /// Decompress the content of the current entry into memory
+    pub fn read_content(&mut self) -> Result<Vec<u8>, String> {
+        let mut content = Vec::new();
+        self.copy_content(&mut content)?;
+        Ok(content)
+    }
+
+    /// Read the raw content of bowl.toml, which must be the first entry
+    pub fn read_config_file(&mut self) -> Result<Vec<u8>, String> {
+        match self.next_entry()? {
+            Some(entry) if entry.file_path == "./bowl.toml" => self.read_content(),
+            _ => Err("bowl.toml must be the first file in the bowl file".into()),
+        }
+    }
+
+    /// Read the bowl.toml config, which must be the first entry
+    pub fn read_config(&mut self) -> Result<Config, String> {
+        parse_config(&self.read_config_file()?)
+    }
+}
+
+/// Read the table and footer left in `inner` and check the archive digest
+fn verify_archive<R: Read>(inner: &mut HashingReader<R>) -> Result<(), String> {
+    let mut rest = Vec::new();
+    inner
+        .get_mut()
+        .read_to_end(&mut rest)
+        .map_err(|e| format!("Failed to read bowlfile: {}", e))?;
+    if rest.len() < FOOTER_LEN {
+        return Err("Unexpected end of bowl file".into());
+    }
+    let (covered, footer) = rest.split_at(rest.len() - DIGEST_LEN - SIGNATURE_LEN);
+    inner.update(covered);
+    if inner.finalize()[..] != footer[..DIGEST_LEN] {
+        return Err("Bowl file is damaged: archive digest mismatch".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<FileContent> {
+        vec![
+            FileContent::new(
+                "./bowl.toml".into(),
+                "[template]\nname = \"t\"\nversion = \"1.0.0\"\nsource = \"\"\n\n[options]\n"
+                    .into(),
+            ),
+            FileContent::new("./main.go".into(), "package main\n".repeat(100).into()),
+            FileContent {
+                mode: 0o755,
+                ..FileContent::new(
+                    "./bin".into(),
+                    vec![BOWL_CHAR, 0xFF, FILE_CHAR, 0, TABLE_CHAR],
+                )
+            },
+            FileContent {
+                kind: EntryKind::Symlink {
+                    target: "bin".into(),
+                },
+                ..FileContent::new("./link".into(), vec![])
+            },
+        ]
+    }
+
+    #[test]
+    fn test_write_read_stream() {
+        for codec in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let mut writer = BowlWriter::new(Vec::new(), codec).unwrap();
+            for f in sample() {
+                writer.write_file(&f).unwrap();
+            }
+            let raw = writer.finish().unwrap();
+
+            // streamed output must match the in memory decoder
+            let bf = BowlFile::decode(raw.clone()).unwrap();
+            assert_eq!(bf.files.len(), 4);
+
+            let mut reader = BowlReader::new(&raw[..]).unwrap();
+            assert_eq!(reader.compression, codec);
+            reader.read_config().unwrap();
+            // skip main.go without reading it
+            assert_eq!(reader.next_entry().unwrap().unwrap().file_path, "./main.go");
+            let entry = reader.next_entry().unwrap().unwrap();
+            assert_eq!(entry.file_path, "./bin");
+            assert_eq!(entry.mode, 0o755);
+            assert_eq!(reader.read_content().unwrap(), sample()[2].content);
+            let entry = reader.next_entry().unwrap().unwrap();
+            assert_eq!(entry.kind, sample()[3].kind);
+            assert!(reader.next_entry().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_read_damaged_stream() {
+        let mut writer = BowlWriter::new(Vec::new(), Compression::None).unwrap();
+        for f in sample() {
+            writer.write_file(&f).unwrap();
+        }
+        let mut raw = writer.finish().unwrap();
+        let pos = raw.windows(12).position(|w| w == b"package main").unwrap();
+        raw[pos] = b'P';
+
+        let mut reader = BowlReader::new(&raw[..]).unwrap();
+        reader.read_config().unwrap();
+        reader.next_entry().unwrap();
+        let err = reader.read_content().unwrap_err();
+        assert!(err.contains("./main.go"), "{}", err);
+
+        // skipping the damaged entry still trips the archive digest
+        let mut reader = BowlReader::new(&raw[..]).unwrap();
+        for _ in 0..sample().len() {
+            assert!(reader.next_entry().unwrap().is_some());
+        }
+        assert!(reader.next_entry().is_err());
+    }
+
+    #[test]
+    fn test_stream_rejects_unsafe_path() {
+        let mut writer = BowlWriter::new(Vec::new(), Compression::Zstd).unwrap();
+        writer.write_file(&sample()[0]).unwrap();
+        writer
+            .write_file(&FileContent::new("./../../.bashrc".into(), "evil".into()))
+            .unwrap();
+        let raw = writer.finish().unwrap();
+
+        let mut reader = BowlReader::new(&raw[..]).unwrap();
+        reader.read_config().unwrap();
+        let err = reader.next_entry().unwrap_err();
+        assert!(err.contains(".bashrc"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_entry_length_mismatch() {
+        let mut writer = BowlWriter::new(Vec::new(), Compression::None).unwrap();
+        assert!(writer
+            .write_entry("./short", &EntryKind::File, 0o644, 10, &b"abc"[..])
+            .is_err());
+    }
+
+    #[test]
+    fn test_write_large_compressed_entry() {
+        // big enough to be spilled to a temp file rather than buffered
+        let content: Vec<u8> = (0..MAX_BUFFERED + 4096).map(|i| (i % 251) as u8).collect();
+        for codec in [Compression::Gzip, Compression::Zstd] {
+            let mut writer = BowlWriter::new(Vec::new(), codec).unwrap();
+            writer.write_file(&sample()[0]).unwrap();
+            writer
+                .write_entry(
+                    "./big.bin",
+                    &EntryKind::File,
+                    0o644,
+                    content.len() as u64,
+                    &content[..],
+                )
+                .unwrap();
+            let raw = writer.finish().unwrap();
+
+            let mut reader = BowlReader::new(&raw[..]).unwrap();
+            reader.read_config().unwrap();
+            reader.next_entry().unwrap().unwrap();
+            assert_eq!(reader.read_content().unwrap(), content);
+            assert!(reader.next_entry().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_write_entry_short_compressed() {
+        let mut writer = BowlWriter::new(Vec::new(), Compression::Zstd).unwrap();
+        assert!(writer
+            .write_entry("./short", &EntryKind::File, 0o644, 10, &b"abc"[..])
+            .is_err());
+    }
+}
@@ -3,7 +3,14 @@ pub const BOWL_CHAR: u8 = 0x9A;
 pub const FILE_CHAR: u8 = 0x9C;
 pub const CONTENT_CHAR: u8 = 0x9E;
 pub const VERSION_CHAR: u8 = 0xA0;
+pub const TABLE_CHAR: u8 = 0xA2;
 
-pub const CURRENT_VERSION: &str = "0.0.1";
+pub const CURRENT_VERSION: &str = "0.1.0";
+pub const LEGACY_VERSION: &str = "0.0.1";
+
+/// Mode given to files when nothing better is known
+pub const DEFAULT_MODE: u32 = 0o644;
+/// Mode given to directories when nothing better is known
+pub const DEFAULT_DIR_MODE: u32 = 0o755;
 
 pub const BOWL_ASCII: &str = include_str!("../../assets/bowl.ascii");
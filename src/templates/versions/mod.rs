@@ -0,0 +1,24 @@
+//! Each supported revision of the bowlfile encoding lives in its own
+//! module. `BowlFile::decode` picks one based on the version header.
+
+pub mod v0_0_1;
+pub mod v0_1_0;
+
+use super::symbols::{BOWL_CHAR, VERSION_CHAR};
+
+/// Read the version string that follows the `BOWL VERSION` preamble
+/// shared by every revision of the format
+pub fn read_version(raw: &[u8]) -> Result<&str, String> {
+    let rest = match raw {
+        [BOWL_CHAR, VERSION_CHAR, rest @ ..] => rest,
+        _ => return Err("Invalid bowl file provided".into()),
+    };
+    let len = rest
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || **b == b'.')
+        .count();
+    match std::str::from_utf8(&rest[..len]) {
+        Ok(version) if version.split('.').count() == 3 => Ok(version),
+        _ => Err("Invalid bowl file version".into()),
+    }
+}
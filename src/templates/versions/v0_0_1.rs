@@ -0,0 +1,214 @@
+//! The original escape-byte encoding. Only decoding is supported so
+//! that bowlfiles produced before 0.1.0 can still be used.
+//!
+//! BOWLFILE: BOWL + VERSION + F1 + F2 + F3 + ...
+//! FILE IN BOWLFILE: BOWL + FILE + <filename> + CONTENT + <escaped file contents>.
+
+use crate::templates::escape::unescape_content;
+use crate::templates::files::{check_entry, EntryKind, FileContent};
+use crate::templates::symbols::{BOWL_CHAR, CONTENT_CHAR, ESC_CHAR, FILE_CHAR, VERSION_CHAR};
+
+#[derive(Clone)]
+enum DecodeState {
+    Reading,
+    ReadingVersionNum(i32),
+    ReadingFileName,
+    ReadingFileContent,
+}
+
+/// Parse a 0.0.1 BowlFile
+/// NOTE: This may be a good use case for Nom
+pub fn decode(raw: &[u8]) -> Result<Vec<FileContent>, String> {
+    let mut version = String::new();
+    let mut files = Vec::new();
+    let mut bytes = raw;
+    let mut reading_state = DecodeState::Reading;
+    let mut file_path = String::new();
+    let mut file_content = Vec::new();
+    loop {
+        match (bytes, reading_state.clone()) {
+            ([x, FILE_CHAR, rest @ ..], DecodeState::ReadingVersionNum(2)) if *x != ESC_CHAR => {
+                version.push(*x as char);
+                reading_state = DecodeState::ReadingFileName;
+                bytes = rest;
+            }
+            ([x @ 46, rest @ ..], DecodeState::ReadingVersionNum(y)) => {
+                reading_state = DecodeState::ReadingVersionNum(y + 1);
+                version.push(*x as char);
+                bytes = rest;
+            }
+            ([x, rest @ ..], DecodeState::ReadingVersionNum(_)) => {
+                version.push(*x as char);
+                bytes = rest;
+            }
+            ([BOWL_CHAR, VERSION_CHAR, rest @ ..], DecodeState::Reading) => {
+                reading_state = DecodeState::ReadingVersionNum(0);
+                bytes = rest;
+            }
+            ([x, FILE_CHAR, rest @ ..], DecodeState::Reading) if *x != ESC_CHAR => {
+                reading_state = DecodeState::ReadingFileName;
+                bytes = rest;
+            }
+            ([x, CONTENT_CHAR, rest @ ..], DecodeState::ReadingFileName) if *x != ESC_CHAR => {
+                file_path.push(*x as char);
+                reading_state = DecodeState::ReadingFileContent;
+                bytes = rest;
+            }
+            ([x, rest @ ..], DecodeState::ReadingFileName) => {
+                file_path.push(*x as char);
+                bytes = rest;
+            }
+            ([x, FILE_CHAR, rest @ ..], DecodeState::ReadingFileContent) if *x != ESC_CHAR => {
+                file_content.push(*x);
+                check_entry(&file_path, &EntryKind::File)?;
+                files.push(FileContent::new(file_path, unescape_content(file_content)));
+                file_path = String::new();
+                file_content = Vec::new();
+                bytes = rest;
+                reading_state = DecodeState::ReadingFileName;
+            }
+            ([x, rest @ ..], DecodeState::ReadingFileContent) => {
+                file_content.push(*x);
+                bytes = rest;
+            }
+            ([], DecodeState::ReadingFileContent) => {
+                check_entry(&file_path, &EntryKind::File)?;
+                files.push(FileContent::new(file_path, unescape_content(file_content)));
+                break;
+            }
+            _ => {
+                return Err("Invalid bowl file provided".into());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_unsafe_paths() {
+        for path in [&b"../../.bashrc"[..], b"/etc/passwd", b"a\0b"] {
+            let mut raw = vec![BOWL_CHAR, VERSION_CHAR];
+            raw.extend_from_slice(b"0.0.1");
+            raw.push(FILE_CHAR);
+            raw.extend_from_slice(path);
+            raw.push(CONTENT_CHAR);
+            raw.extend_from_slice(b"evil");
+            assert!(decode(&raw).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decode_content() {
+        let expected = [
+            BOWL_CHAR,
+            VERSION_CHAR,
+            48,
+            46,
+            48,
+            46,
+            49,
+            FILE_CHAR,
+            82,
+            69,
+            65,
+            68,
+            77,
+            69,
+            46,
+            109,
+            100,
+            CONTENT_CHAR,
+            35,
+            32,
+            77,
+            121,
+            32,
+            101,
+            112,
+            105,
+            99,
+            32,
+            114,
+            101,
+            97,
+            100,
+            109,
+            101,
+            10,
+            71,
+            97,
+            109,
+            101,
+            114,
+            FILE_CHAR,
+            115,
+            114,
+            99,
+            47,
+            109,
+            97,
+            105,
+            110,
+            46,
+            114,
+            115,
+            CONTENT_CHAR,
+            112,
+            117,
+            98,
+            32,
+            102,
+            110,
+            32,
+            109,
+            97,
+            105,
+            110,
+            40,
+            41,
+            32,
+            123,
+            112,
+            114,
+            105,
+            110,
+            116,
+            108,
+            110,
+            33,
+            40,
+            34,
+            72,
+            101,
+            108,
+            108,
+            111,
+            44,
+            32,
+            119,
+            111,
+            114,
+            108,
+            100,
+            34,
+            41,
+            125,
+        ];
+
+        let files = decode(&expected).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file_path, "README.md");
+        assert_eq!(files[0].content, b"# My epic readme\nGamer");
+        assert_eq!(files[1].file_path, "src/main.rs");
+        assert_eq!(
+            files[1].content,
+            b"pub fn main() {println!(\"Hello, world\")}"
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! Length-prefixed encoding introduced in 0.1.0
+//!
+//! Every integer is little endian. Capitalized names are constants
+//! from `symbols.rs`.
+//!
+//! BOWLFILE: HEADER + ENTRY + ENTRY + ... + TABLE + FOOTER
+//! HEADER: BOWL + VERSION + "0.1.0" + <compression u8>
+//! ENTRY: FILE + <meta> + <length u64> + <payload> + <digest>
+//! TABLE: TABLE + <count u32> + (<meta> + <digest> + <offset u64> + <length u64>) * count
+//! FOOTER: <table offset u64> + <archive digest> + <signature>
+//! META: <kind u8> + <path> + <mode u32> + <link target>
+//! PATH, LINK TARGET: <len u16> + <utf-8 bytes>
+//! SIGNATURE: <ed25519 public key 32> + <ed25519 signature 64>
+//!
+//! Kind is one of file, directory or symlink (see `EntryKind`). Only
+//! files have a payload, and only symlinks have a non-empty link target.
+//! Paths are relative, and readers refuse any path that is absolute,
+//! has a `..` component or a NUL byte in it (see `check_entry`).
+//!
+//! Digests are SHA-256. An entry's digest is taken over its uncompressed
+//! content and comes after the payload, since it's only known once the
+//! content has been read. The archive digest covers every byte before it.
+//! The signature is made over the archive digest, and is all zeros when
+//! the bowlfile isn't signed (see `signature.rs`).
+//!
+//! Payloads are stored raw, or compressed one at a time with the codec
+//! named in the header, so nothing has to be escaped. The table records
+//! the absolute offset and stored length of every payload, which lets a
+//! reader jump straight to one file without touching the others. Each
+//! entry also carries its own header so the archive can be read front
+//! to back.
+//!
+//! Writing, and reading front to back, is done by the types in `stream.rs`.
+
+use crate::templates::compression::Compression;
+use crate::templates::digest::{digest, Digest};
+use crate::templates::files::{check_entry, EntryKind, FileContent};
+use crate::templates::signature::SignatureBlock;
+use crate::templates::symbols::{CURRENT_VERSION, TABLE_CHAR};
+
+/// Size of the fixed header at the start of the file
+pub const HEADER_LEN: usize = 2 + CURRENT_VERSION.len() + 1;
+/// Size of a digest
+pub const DIGEST_LEN: usize = 32;
+/// Size of the signature block
+pub const SIGNATURE_LEN: usize = 32 + 64;
+/// Size of the footer at the end of the file
+pub const FOOTER_LEN: usize = 8 + DIGEST_LEN + SIGNATURE_LEN;
+
+/// Location and metadata of a single entry within a bowlfile
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableEntry {
+    pub file_path: String,
+    pub kind: EntryKind,
+    pub mode: u32,
+    /// Digest of the uncompressed content
+    pub digest: Digest,
+    /// Absolute position of the payload within the bowlfile
+    pub offset: u64,
+    /// Length of the payload as stored, after compression
+    pub length: u64,
+}
+
+/// Small helper for pulling little endian values out of a byte slice
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8], pos: usize) -> Self {
+        Self { bytes, pos }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("Unexpected end of bowl file")?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn digest(&mut self) -> Result<Digest, String> {
+        Ok(self.take(DIGEST_LEN)?.try_into().unwrap())
+    }
+
+    fn path(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|e| format!("Invalid file name in bowl file: {}", e))
+    }
+}
+
+/// Read the compression codec named in the header of a 0.1.0 bowlfile
+pub fn read_compression(raw: &[u8]) -> Result<Compression, String> {
+    match raw.get(HEADER_LEN - 1) {
+        Some(byte) => Compression::from_byte(*byte),
+        None => Err("Invalid bowl file provided".into()),
+    }
+}
+
+/// Read the file table of a 0.1.0 bowlfile without touching any payload
+pub fn read_table(raw: &[u8]) -> Result<Vec<TableEntry>, String> {
+    if raw.len() < HEADER_LEN + FOOTER_LEN {
+        return Err("Invalid bowl file provided".into());
+    }
+    let mut footer = ByteReader::new(raw, raw.len() - FOOTER_LEN);
+    let table_offset = footer.u64()?;
+    if table_offset < HEADER_LEN as u64 || table_offset > (raw.len() - FOOTER_LEN) as u64 {
+        return Err("Invalid file table offset in bowl file".into());
+    }
+
+    let mut reader = ByteReader::new(&raw[..raw.len() - FOOTER_LEN], table_offset as usize);
+    if reader.u8()? != TABLE_CHAR {
+        return Err("Invalid file table in bowl file".into());
+    }
+    let count = reader.u32()?;
+    let mut table = Vec::new();
+    for _ in 0..count {
+        let kind = reader.u8()?;
+        let file_path = reader.path()?;
+        let mode = reader.u32()?;
+        let kind = EntryKind::from_byte(kind, reader.path()?)?;
+        check_entry(&file_path, &kind)?;
+        let digest = reader.digest()?;
+        let offset = reader.u64()?;
+        let length = reader.u64()?;
+        match length
+            .checked_add(DIGEST_LEN as u64)
+            .and_then(|l| offset.checked_add(l))
+        {
+            Some(end) if offset >= HEADER_LEN as u64 && end <= table_offset => {}
+            _ => return Err(format!("Invalid payload bounds for \"{}\"", file_path)),
+        }
+        table.push(TableEntry {
+            file_path,
+            kind,
+            mode,
+            digest,
+            offset,
+            length,
+        });
+    }
+
+    Ok(table)
+}
+
+/// Digest stored at the end of a 0.1.0 bowlfile
+pub fn read_archive_digest(raw: &[u8]) -> Result<Digest, String> {
+    if raw.len() < HEADER_LEN + FOOTER_LEN {
+        return Err("Invalid bowl file provided".into());
+    }
+    ByteReader::new(raw, raw.len() - DIGEST_LEN - SIGNATURE_LEN).digest()
+}
+
+/// Whether the archive digest matches the rest of the bowlfile
+pub fn archive_intact(raw: &[u8]) -> Result<bool, String> {
+    Ok(read_archive_digest(raw)? == digest(&raw[..raw.len() - DIGEST_LEN - SIGNATURE_LEN]))
+}
+
+/// Signature block at the end of a 0.1.0 bowlfile, if it was signed.
+/// The signature itself isn't checked.
+pub fn read_signature(raw: &[u8]) -> Result<Option<SignatureBlock>, String> {
+    if raw.len() < HEADER_LEN + FOOTER_LEN {
+        return Err("Invalid bowl file provided".into());
+    }
+    let block = ByteReader::new(raw, raw.len() - SIGNATURE_LEN).take(SIGNATURE_LEN)?;
+    Ok(SignatureBlock::from_bytes(block.try_into().unwrap()))
+}
+
+/// Get the stored (possibly compressed) payload of a single entry out
+/// of a 0.1.0 bowlfile
+pub fn read_entry<'a>(raw: &'a [u8], entry: &TableEntry) -> &'a [u8] {
+    &raw[entry.offset as usize..(entry.offset + entry.length) as usize]
+}
+
+/// Decompress the content of a single entry, checking it against its
+/// digest. Errors describe the damage without naming the entry.
+fn check_content(
+    raw: &[u8],
+    compression: Compression,
+    entry: &TableEntry,
+) -> Result<Vec<u8>, String> {
+    // directories and symlinks have nothing stored at all
+    let content = match entry.length {
+        0 => Vec::new(),
+        _ => compression.decompress(read_entry(raw, entry))?,
+    };
+    if digest(&content) != entry.digest {
+        return Err("checksum mismatch".into());
+    }
+    Ok(content)
+}
+
+/// Decompress the content of a single entry, checking it against its digest
+pub fn read_content(
+    raw: &[u8],
+    compression: Compression,
+    entry: &TableEntry,
+) -> Result<Vec<u8>, String> {
+    check_content(raw, compression, entry)
+        .map_err(|e| format!("\"{}\" is damaged: {}", entry.file_path, e))
+}
+
+/// Outcome of checking every digest in a bowlfile
+pub struct Verification {
+    pub archive_digest: Digest,
+    pub archive_intact: bool,
+    pub signature: Option<SignatureBlock>,
+    /// Every entry, along with why it's damaged if it is
+    pub entries: Vec<(String, Option<String>)>,
+}
+
+/// Check every digest in a 0.1.0 bowlfile, reporting all damaged entries
+/// rather than stopping at the first one
+pub fn verify(raw: &[u8]) -> Result<Verification, String> {
+    let compression = read_compression(raw)?;
+    let entries = read_table(raw)?
+        .iter()
+        .map(|entry| {
+            let damage = check_content(raw, compression, entry).err();
+            (entry.file_path.clone(), damage)
+        })
+        .collect();
+    Ok(Verification {
+        archive_digest: read_archive_digest(raw)?,
+        archive_intact: archive_intact(raw)?,
+        signature: read_signature(raw)?,
+        entries,
+    })
+}
+
+/// Parse a 0.1.0 BowlFile, decompressing and verifying every payload
+pub fn decode(raw: &[u8]) -> Result<(Compression, Vec<FileContent>), String> {
+    let compression = read_compression(raw)?;
+    let files = read_table(raw)?
+        .into_iter()
+        .map(|entry| {
+            let content = read_content(raw, compression, &entry)?;
+            Ok(FileContent {
+                content,
+                file_path: entry.file_path,
+                mode: entry.mode,
+                kind: entry.kind,
+            })
+        })
+        .collect::<Result<Vec<FileContent>, String>>()?;
+    if !archive_intact(raw)? {
+        return Err("Bowl file is damaged: archive digest mismatch".into());
+    }
+    Ok((compression, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::stream::BowlWriter;
+    use crate::templates::symbols::{BOWL_CHAR, DEFAULT_DIR_MODE, FILE_CHAR};
+
+    fn encode(files: &[FileContent], compression: Compression) -> Result<Vec<u8>, String> {
+        let mut writer = BowlWriter::new(Vec::new(), compression)?;
+        for f in files {
+            writer.write_file(f)?;
+        }
+        writer.finish()
+    }
+
+    fn sample() -> Vec<FileContent> {
+        vec![
+            FileContent::new("README.md".into(), "# My epic readme\nGamer".into()),
+            FileContent {
+                mode: 0o755,
+                ..FileContent::new(
+                    "bin/blob".into(),
+                    vec![BOWL_CHAR, 0xFF, FILE_CHAR, 0, TABLE_CHAR],
+                )
+            },
+            FileContent::new("empty".into(), vec![]),
+            FileContent {
+                mode: DEFAULT_DIR_MODE,
+                kind: EntryKind::Directory,
+                ..FileContent::new("empty_dir".into(), vec![])
+            },
+            FileContent {
+                mode: 0o777,
+                kind: EntryKind::Symlink {
+                    target: "bin/blob".into(),
+                },
+                ..FileContent::new("link".into(), vec![])
+            },
+        ]
+    }
+
+    #[test]
+    fn test_encode_decode_content() {
+        let files = sample();
+        for codec in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let raw = encode(&files, codec).unwrap();
+            let (compression, decoded) = decode(&raw).unwrap();
+
+            assert_eq!(compression, codec);
+            assert_eq!(files.len(), decoded.len());
+            for (a, b) in files.iter().zip(decoded.iter()) {
+                assert_eq!(a.file_path, b.file_path);
+                assert_eq!(a.content, b.content);
+                assert_eq!(a.mode, b.mode);
+                assert_eq!(a.kind, b.kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_single_entry() {
+        let raw = encode(&sample(), Compression::None).unwrap();
+        let table = read_table(&raw).unwrap();
+
+        assert_eq!(table.len(), 5);
+        assert_eq!(table[1].file_path, "bin/blob");
+        assert_eq!(table[1].mode, 0o755);
+        assert_eq!(
+            read_entry(&raw, &table[1]),
+            &[BOWL_CHAR, 0xFF, FILE_CHAR, 0, TABLE_CHAR]
+        );
+    }
+
+    #[test]
+    fn test_reject_truncated() {
+        let raw = encode(&sample(), Compression::None).unwrap();
+        for len in [0, HEADER_LEN, raw.len() / 2, raw.len() - 1] {
+            assert!(decode(&raw[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_detect_damaged_entry() {
+        let mut raw = encode(&sample(), Compression::None).unwrap();
+        let table = read_table(&raw).unwrap();
+        raw[table[0].offset as usize] ^= 0xFF;
+
+        let err = decode(&raw).unwrap_err();
+        assert!(err.contains("README.md"), "{}", err);
+
+        let verification = verify(&raw).unwrap();
+        assert!(!verification.archive_intact);
+        assert!(verification.entries[0].1.is_some());
+        assert!(verification.entries[1..].iter().all(|(_, e)| e.is_none()));
+    }
+
+    #[test]
+    fn test_reject_unsafe_paths() {
+        for path in [
+            "../../.bashrc",
+            "./a/../../b",
+            "/etc/passwd",
+            "C:/evil",
+            "a\0b",
+        ] {
+            let raw = encode(&[FileContent::new(path.into(), vec![])], Compression::None).unwrap();
+            assert!(read_table(&raw).is_err(), "{}", path);
+            assert!(decode(&raw).is_err(), "{}", path);
+        }
+
+        // links may climb out of their own directory, but not be absolute
+        let link = |target: &str| FileContent {
+            kind: EntryKind::Symlink {
+                target: target.into(),
+            },
+            ..FileContent::new("./docs/link".into(), vec![])
+        };
+        let raw = encode(&[link("../README.md")], Compression::None).unwrap();
+        assert!(decode(&raw).is_ok());
+        let raw = encode(&[link("/etc/passwd")], Compression::None).unwrap();
+        assert!(decode(&raw).is_err());
+    }
+
+    #[test]
+    fn test_reject_overflowing_length() {
+        let mut raw = encode(&sample()[..1], Compression::None).unwrap();
+        // the length of the only table entry sits right before the footer
+        let end = raw.len() - FOOTER_LEN;
+        raw[end - 8..end].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+
+        let err = read_table(&raw).unwrap_err();
+        assert!(err.contains("README.md"), "{}", err);
+    }
+}